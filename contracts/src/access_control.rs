@@ -0,0 +1,174 @@
+use soroban_sdk::{contractevent, contracttype, symbol_short, Address, Env, Symbol};
+
+// Roles shared by the protocol contracts.
+// The owner passes every role check; roles let the owner delegate single duties.
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Role {
+    Admin = 0,       // manages every other role and writes off bad debt
+    Verifier = 1,    // verifies identities
+    RiskManager = 2, // rewards and penalizes credit scores
+    Pauser = 3,      // toggles pause flags
+    Treasurer = 4,   // moves protocol funds
+}
+
+#[contractevent]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoleGranted {
+    #[topic]
+    pub role: Role,
+    #[topic]
+    pub account: Address,
+    pub sender: Address,
+}
+
+#[contractevent]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoleRevoked {
+    #[topic]
+    pub role: Role,
+    #[topic]
+    pub account: Address,
+    pub sender: Address,
+}
+
+#[contractevent]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnershipTransferStarted {
+    #[topic]
+    pub previous_owner: Address,
+    #[topic]
+    pub new_owner: Address,
+}
+
+#[contractevent]
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnershipTransferred {
+    #[topic]
+    pub previous_owner: Address,
+    #[topic]
+    pub new_owner: Address,
+}
+
+const OWNER: Symbol = symbol_short!("OWNER");
+const PENDING_OWNER: Symbol = symbol_short!("PEND_OWN");
+
+/// Store the initial owner (panics if the contract is already initialized)
+pub fn init_owner(env: &Env, owner: &Address) {
+    if has_owner(env) {
+        panic!("Already initialized");
+    }
+    env.storage().instance().set(&OWNER, owner);
+}
+
+/// Check if an owner has been set
+pub fn has_owner(env: &Env) -> bool {
+    env.storage().instance().has(&OWNER)
+}
+
+/// Get the owner of the contract
+pub fn get_owner(env: &Env) -> Address {
+    env.storage()
+        .instance()
+        .get(&OWNER)
+        .expect("Contract not initialized")
+}
+
+/// Require auth from the owner
+pub fn require_owner(env: &Env) {
+    get_owner(env).require_auth();
+}
+
+/// Check if an account was granted a role (the owner is not implicitly listed)
+pub fn has_role(env: &Env, role: Role, account: &Address) -> bool {
+    let role_key = (symbol_short!("ROLE"), role, account.clone());
+    env.storage().persistent().has(&role_key)
+}
+
+/// Require auth from `caller` and check it is the owner or holds `role`
+pub fn require_role(env: &Env, role: Role, caller: &Address) {
+    caller.require_auth();
+
+    if *caller != get_owner(env) && !has_role(env, role, caller) {
+        panic!("Caller is missing required role");
+    }
+}
+
+/// Check `caller` may manage `role`: only the owner manages Admin, admins manage the rest
+fn require_role_manager(env: &Env, role: Role, caller: &Address) {
+    if role == Role::Admin {
+        caller.require_auth();
+        if *caller != get_owner(env) {
+            panic!("Only the owner can manage admins");
+        }
+    } else {
+        require_role(env, Role::Admin, caller);
+    }
+}
+
+/// Grant a role to an account
+pub fn grant_role(env: &Env, caller: &Address, role: Role, account: &Address) {
+    require_role_manager(env, role, caller);
+
+    let role_key = (symbol_short!("ROLE"), role, account.clone());
+    env.storage().persistent().set(&role_key, &true);
+
+    RoleGranted {
+        role,
+        account: account.clone(),
+        sender: caller.clone(),
+    }
+    .publish(env);
+}
+
+/// Revoke a role from an account
+pub fn revoke_role(env: &Env, caller: &Address, role: Role, account: &Address) {
+    require_role_manager(env, role, caller);
+
+    let role_key = (symbol_short!("ROLE"), role, account.clone());
+    env.storage().persistent().remove(&role_key);
+
+    RoleRevoked {
+        role,
+        account: account.clone(),
+        sender: caller.clone(),
+    }
+    .publish(env);
+}
+
+/// Start a two-step ownership transfer (only owner)
+/// The new owner must call accept_ownership to complete it
+pub fn transfer_ownership(env: &Env, new_owner: &Address) {
+    let owner = get_owner(env);
+    owner.require_auth();
+
+    env.storage().instance().set(&PENDING_OWNER, new_owner);
+
+    OwnershipTransferStarted {
+        previous_owner: owner,
+        new_owner: new_owner.clone(),
+    }
+    .publish(env);
+}
+
+/// Get the pending owner of an unfinished ownership transfer
+pub fn get_pending_owner(env: &Env) -> Option<Address> {
+    env.storage().instance().get(&PENDING_OWNER)
+}
+
+/// Complete an ownership transfer (only the pending owner)
+pub fn accept_ownership(env: &Env) {
+    let new_owner = get_pending_owner(env).expect("No pending ownership transfer");
+    new_owner.require_auth();
+
+    let previous_owner = get_owner(env);
+    env.storage().instance().set(&OWNER, &new_owner);
+    env.storage().instance().remove(&PENDING_OWNER);
+
+    OwnershipTransferred {
+        previous_owner,
+        new_owner,
+    }
+    .publish(env);
+}
@@ -1,4 +1,6 @@
-use soroban_sdk::{contract, contractimpl, contracttype, symbol_short, Address, Env, Symbol};
+use crate::access_control::{self, Role};
+use crate::pause::{self, PauseFlag};
+use soroban_sdk::{contract, contractimpl, contracttype, symbol_short, Address, Env, Symbol, Vec};
 
 #[derive(Clone)]
 #[contracttype]
@@ -8,12 +10,13 @@ pub struct CreditScore {
     pub blacklisted: bool,
 }
 
-const OWNER: Symbol = symbol_short!("OWNER");
 const INITIAL_SCORE: i128 = 300;
 // Ratio: 1000 USDC (1,000,000,000 smallest units with 6 decimals) / 300 score = 3,333,333
 // This means: 1 score point = 3,333,333 smallest units = 3.333333 USDC
 // So 300 score = 1,000,000,000 smallest units = 1000 USDC
 const SCORE_TO_AMOUNT_RATIO: i128 = 3_333_333;
+const SCR_COUNT: Symbol = symbol_short!("SCR_COUNT"); // Number of entries in the score index
+const MAX_PAGE_SIZE: u32 = 50; // Upper bound on entries returned by list_scores
 
 #[contract]
 pub struct CreditScoringMini;
@@ -22,27 +25,70 @@ pub struct CreditScoringMini;
 impl CreditScoringMini {
     /// Initialize the contract with an owner
     pub fn initialize(env: Env, owner: Address) {
-        env.storage().instance().set(&OWNER, &owner);
+        access_control::init_owner(&env, &owner);
     }
 
     /// Get the owner of the contract
     pub fn get_owner(env: Env) -> Address {
-        env.storage()
-            .instance()
-            .get(&OWNER)
-            .expect("Contract not initialized")
+        access_control::get_owner(&env)
+    }
+
+    /// Start a two-step ownership transfer (only owner)
+    /// The new owner must call accept_ownership to complete it
+    pub fn transfer_ownership(env: Env, new_owner: Address) {
+        access_control::transfer_ownership(&env, &new_owner);
+    }
+
+    /// Complete a pending ownership transfer (only the pending owner)
+    pub fn accept_ownership(env: Env) {
+        access_control::accept_ownership(&env);
+    }
+
+    /// Get the pending owner of an unfinished ownership transfer
+    pub fn get_pending_owner(env: Env) -> Option<Address> {
+        access_control::get_pending_owner(&env)
+    }
+
+    /// Grant a role (owner, or an admin for non-admin roles)
+    pub fn grant_role(env: Env, caller: Address, role: Role, account: Address) {
+        access_control::grant_role(&env, &caller, role, &account);
     }
 
-    /// Check if caller is owner
-    fn require_owner(env: &Env) {
-        let owner = Self::get_owner(env.clone());
-        owner.require_auth();
+    /// Revoke a role (owner, or an admin for non-admin roles)
+    pub fn revoke_role(env: Env, caller: Address, role: Role, account: Address) {
+        access_control::revoke_role(&env, &caller, role, &account);
+    }
+
+    /// Check if an account was granted a role
+    pub fn has_role(env: Env, role: Role, account: Address) -> bool {
+        access_control::has_role(&env, role, &account)
+    }
+
+    /// Pause an operation (owner or pauser)
+    /// Pausing ScoreUpdates also stops loan manager calls that move scores (repayments,
+    /// defaults); revoking a compromised risk manager is the narrower fix.
+    pub fn pause(env: Env, caller: Address, flag: PauseFlag) {
+        access_control::require_role(&env, Role::Pauser, &caller);
+        pause::set_paused(&env, flag, true);
+    }
+
+    /// Unpause an operation (owner or pauser)
+    pub fn unpause(env: Env, caller: Address, flag: PauseFlag) {
+        access_control::require_role(&env, Role::Pauser, &caller);
+        pause::set_paused(&env, flag, false);
+    }
+
+    /// Check if an operation is paused
+    pub fn is_paused(env: Env, flag: PauseFlag) -> bool {
+        pause::is_paused(&env, flag)
     }
 
     /// Initialize credit score for a new user
     /// Note: The platform (owner) must deposit funds into the lending pool separately
     /// This function only initializes the credit score
     pub fn initialize_user(env: Env, user: Address) {
+        pause::require_not_paused(&env, PauseFlag::ScoreUpdates);
+
         if Self::has_score(env.clone(), user.clone()) {
             panic!("User already has a credit score");
         }
@@ -59,6 +105,55 @@ impl CreditScoringMini {
         env.storage()
             .persistent()
             .set(&score_key, &score);
+        Self::append_to_index(&env, &user);
+    }
+
+    /// Add a user to the sequential score index (no-op if already indexed)
+    fn append_to_index(env: &Env, user: &Address) {
+        let pos_key = (symbol_short!("SCR_POS"), user.clone());
+        if env.storage().persistent().has(&pos_key) {
+            return;
+        }
+
+        let count: u32 = env.storage().instance().get(&SCR_COUNT).unwrap_or(0);
+        let idx_key = (symbol_short!("SCR_IDX"), count);
+        env.storage().persistent().set(&idx_key, user);
+        env.storage().persistent().set(&pos_key, &count);
+        env.storage().instance().set(&SCR_COUNT, &(count + 1));
+    }
+
+    /// Index a score initialized before the score index existed
+    /// Anyone can call this: it only records an existing score in the index
+    pub fn index_score(env: Env, user: Address) {
+        if !Self::has_score(env.clone(), user.clone()) {
+            panic!("Credit score not found");
+        }
+        Self::append_to_index(&env, &user);
+    }
+
+    /// Get number of users in the score index
+    pub fn get_scored_count(env: Env) -> u32 {
+        env.storage().instance().get(&SCR_COUNT).unwrap_or(0)
+    }
+
+    /// List credit scores in initialization order
+    /// Returns at most `limit` entries (capped at MAX_PAGE_SIZE) starting at index `start`
+    pub fn list_scores(env: Env, start: u32, limit: u32) -> Vec<(Address, CreditScore)> {
+        let count = Self::get_scored_count(env.clone());
+        let end = start.saturating_add(limit.min(MAX_PAGE_SIZE)).min(count);
+
+        let mut page = Vec::new(&env);
+        for index in start..end {
+            let idx_key = (symbol_short!("SCR_IDX"), index);
+            let user: Address = env
+                .storage()
+                .persistent()
+                .get(&idx_key)
+                .expect("Score index corrupted");
+            let score = Self::get_score_internal(&env, &user);
+            page.push_back((user, score));
+        }
+        page
     }
 
     /// Check if user has a credit score
@@ -108,9 +203,10 @@ impl CreditScoringMini {
         score.blacklisted
     }
 
-    /// Reward user (increase score)
-    pub fn reward(env: Env, user: Address, points: i128) {
-        Self::require_owner(&env);
+    /// Reward user (increase score) (owner or risk manager)
+    pub fn reward(env: Env, caller: Address, user: Address, points: i128) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+        pause::require_not_paused(&env, PauseFlag::ScoreUpdates);
 
         if points <= 0 {
             panic!("Points must be positive");
@@ -126,9 +222,10 @@ impl CreditScoringMini {
             .set(&score_key, &score);
     }
 
-    /// Penalize user (decrease score and potentially blacklist)
-    pub fn penalize_user(env: Env, user: Address, points: i128) {
-        Self::require_owner(&env);
+    /// Penalize user (decrease score and potentially blacklist) (owner or risk manager)
+    pub fn penalize_user(env: Env, caller: Address, user: Address, points: i128) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+        pause::require_not_paused(&env, PauseFlag::ScoreUpdates);
 
         if points <= 0 {
             panic!("Points must be positive");
@@ -150,12 +247,6 @@ impl CreditScoringMini {
             .persistent()
             .set(&score_key, &score);
     }
-
-    /// Transfer ownership of the contract
-    pub fn transfer_ownership(env: Env, new_owner: Address) {
-        Self::require_owner(&env);
-        env.storage().instance().set(&OWNER, &new_owner);
-    }
 }
 
 #[cfg(test)]
@@ -164,6 +255,7 @@ mod test {
     use soroban_sdk::testutils::Address as _;
 
     #[test]
+    #[allow(deprecated, clippy::bool_assert_comparison)]
     fn test_credit_scoring() {
         let env = Env::default();
         let contract_id = env.register_contract(None, CreditScoringMini);
@@ -187,5 +279,101 @@ mod test {
         // Note: reward_user and penalize_user require owner auth
         // In real test, you'd need to set owner as invoker
     }
+
+    #[test]
+    fn test_roles_and_ownership_transfer() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register(CreditScoringMini, ());
+        let client = CreditScoringMiniClient::new(&env, &contract_id);
+        let owner = Address::generate(&env);
+        client.initialize(&owner);
+
+        let user = Address::generate(&env);
+        client.initialize_user(&user);
+
+        // Risk managers can adjust scores, other accounts cannot
+        let risk_manager = Address::generate(&env);
+        assert!(client.try_reward(&risk_manager, &user, &10).is_err());
+        client.grant_role(&owner, &Role::RiskManager, &risk_manager);
+        assert!(client.has_role(&Role::RiskManager, &risk_manager));
+        client.reward(&risk_manager, &user, &10);
+        client.penalize_user(&risk_manager, &user, &5);
+        assert_eq!(client.get_score_value(&user), 305);
+
+        // Admins manage non-admin roles but cannot create other admins
+        let admin = Address::generate(&env);
+        client.grant_role(&owner, &Role::Admin, &admin);
+        client.revoke_role(&admin, &Role::RiskManager, &risk_manager);
+        assert!(!client.has_role(&Role::RiskManager, &risk_manager));
+        assert!(client
+            .try_grant_role(&admin, &Role::Admin, &risk_manager)
+            .is_err());
+
+        // Ownership moves only once the new owner accepts
+        let new_owner = Address::generate(&env);
+        client.transfer_ownership(&new_owner);
+        assert_eq!(client.get_owner(), owner);
+        assert_eq!(client.get_pending_owner(), Some(new_owner.clone()));
+        client.accept_ownership();
+        assert_eq!(client.get_owner(), new_owner);
+        assert_eq!(client.get_pending_owner(), None);
+    }
+
+    #[test]
+    fn test_pause_score_updates() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register(CreditScoringMini, ());
+        let client = CreditScoringMiniClient::new(&env, &contract_id);
+        let owner = Address::generate(&env);
+        client.initialize(&owner);
+
+        let user = Address::generate(&env);
+        client.initialize_user(&user);
+
+        let pauser = Address::generate(&env);
+        client.grant_role(&owner, &Role::Pauser, &pauser);
+        client.pause(&pauser, &PauseFlag::ScoreUpdates);
+        assert!(client.is_paused(&PauseFlag::ScoreUpdates));
+
+        assert!(client.try_initialize_user(&Address::generate(&env)).is_err());
+        assert!(client.try_reward(&owner, &user, &10).is_err());
+        assert!(client.try_penalize_user(&owner, &user, &10).is_err());
+        // Reads stay available
+        assert_eq!(client.get_score_value(&user), 300);
+
+        // Only the owner or a pauser can toggle flags
+        let stranger = Address::generate(&env);
+        assert!(client.try_unpause(&stranger, &PauseFlag::ScoreUpdates).is_err());
+
+        client.unpause(&pauser, &PauseFlag::ScoreUpdates);
+        client.reward(&owner, &user, &10);
+        assert_eq!(client.get_score_value(&user), 310);
+    }
+
+    #[test]
+    fn test_list_scores() {
+        let env = Env::default();
+        let contract_id = env.register(CreditScoringMini, ());
+        let client = CreditScoringMiniClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        let first = Address::generate(&env);
+        let second = Address::generate(&env);
+        client.initialize_user(&first);
+        client.initialize_user(&second);
+
+        assert_eq!(client.get_scored_count(), 2);
+
+        let page = client.list_scores(&0, &10);
+        assert_eq!(page.len(), 2);
+        assert_eq!(page.get(0).unwrap().0, first);
+        assert_eq!(page.get(1).unwrap().0, second);
+        assert_eq!(page.get(1).unwrap().1.score, 300);
+
+        assert_eq!(client.list_scores(&1, &10).len(), 1);
+        assert_eq!(client.list_scores(&2, &10).len(), 0);
+    }
 }
 
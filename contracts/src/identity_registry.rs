@@ -1,7 +1,13 @@
-use soroban_sdk::{contract, contractimpl, contracttype, symbol_short, Address, Env, String, Symbol};
+use crate::access_control::{self, Role};
+use crate::pause::{self, PauseFlag};
+use soroban_sdk::{
+    contract, contractimpl, contracttype, symbol_short, Address, Env, String, Symbol, TryFromVal,
+    Val, Vec,
+};
 
-#[derive(Clone, Copy)]
-#[repr(u8)]
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u32)]
 pub enum VerificationLevel {
     Unverified = 0,
     Level1 = 1,
@@ -9,19 +15,57 @@ pub enum VerificationLevel {
     Level3 = 3,
 }
 
+impl VerificationLevel {
+    /// Map the raw integer stored by the legacy record layout
+    fn from_legacy(level: i32) -> Self {
+        match level {
+            0 => VerificationLevel::Unverified,
+            1 => VerificationLevel::Level1,
+            2 => VerificationLevel::Level2,
+            3 => VerificationLevel::Level3,
+            _ => panic!("Unknown legacy verification level"),
+        }
+    }
+}
+
 // Minimal struct stored on-chain - only CID and basic verification info
 #[derive(Clone)]
 #[contracttype]
 pub struct IdentityRecord {
     pub ipfs_cid: String,        // CID of all personal data stored in IPFS
     pub dni: String,             // DNI stored for duplicate checking
-    pub verification_level: i32,  // Changed from u8 to i32 for Soroban compatibility
+    pub verification_level: VerificationLevel,
     pub verified: bool,
     pub created_at: i128,         // Changed from u64 to i128 for Soroban compatibility
 }
 
-const OWNER: Symbol = symbol_short!("OWNER");
+// Record layout written before verification_level became a contracttype enum.
+// Only used to read and migrate old entries, never written.
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct LegacyIdentityRecord {
+    pub ipfs_cid: String,
+    pub dni: String,
+    pub verification_level: i32,
+    pub verified: bool,
+    pub created_at: i128,
+}
+
+impl From<LegacyIdentityRecord> for IdentityRecord {
+    fn from(legacy: LegacyIdentityRecord) -> Self {
+        IdentityRecord {
+            ipfs_cid: legacy.ipfs_cid,
+            dni: legacy.dni,
+            verification_level: VerificationLevel::from_legacy(legacy.verification_level),
+            verified: legacy.verified,
+            created_at: legacy.created_at,
+        }
+    }
+}
+
 const TOT_USERS: Symbol = symbol_short!("TOT_USERS");
+const USR_COUNT: Symbol = symbol_short!("USR_COUNT"); // Number of entries in the user index
+const MAX_PAGE_SIZE: u32 = 50; // Upper bound on entries returned by list_identities
 
 #[contract]
 pub struct IdentityRegistry;
@@ -30,22 +74,61 @@ pub struct IdentityRegistry;
 impl IdentityRegistry {
     /// Initialize the contract with an owner
     pub fn initialize(env: Env, owner: Address) {
-        env.storage().instance().set(&OWNER, &owner);
+        access_control::init_owner(&env, &owner);
         env.storage().instance().set(&TOT_USERS, &0i128);
     }
 
     /// Get the owner of the contract
     pub fn get_owner(env: Env) -> Address {
-        env.storage()
-            .instance()
-            .get(&OWNER)
-            .expect("Contract not initialized")
+        access_control::get_owner(&env)
     }
 
-    /// Check if caller is owner
-    fn require_owner(env: &Env) {
-        let owner = Self::get_owner(env.clone());
-        owner.require_auth();
+    /// Start a two-step ownership transfer (only owner)
+    /// The new owner must call accept_ownership to complete it
+    pub fn transfer_ownership(env: Env, new_owner: Address) {
+        access_control::transfer_ownership(&env, &new_owner);
+    }
+
+    /// Complete a pending ownership transfer (only the pending owner)
+    pub fn accept_ownership(env: Env) {
+        access_control::accept_ownership(&env);
+    }
+
+    /// Get the pending owner of an unfinished ownership transfer
+    pub fn get_pending_owner(env: Env) -> Option<Address> {
+        access_control::get_pending_owner(&env)
+    }
+
+    /// Grant a role (owner, or an admin for non-admin roles)
+    pub fn grant_role(env: Env, caller: Address, role: Role, account: Address) {
+        access_control::grant_role(&env, &caller, role, &account);
+    }
+
+    /// Revoke a role (owner, or an admin for non-admin roles)
+    pub fn revoke_role(env: Env, caller: Address, role: Role, account: Address) {
+        access_control::revoke_role(&env, &caller, role, &account);
+    }
+
+    /// Check if an account was granted a role
+    pub fn has_role(env: Env, role: Role, account: Address) -> bool {
+        access_control::has_role(&env, role, &account)
+    }
+
+    /// Pause an operation (owner or pauser)
+    pub fn pause(env: Env, caller: Address, flag: PauseFlag) {
+        access_control::require_role(&env, Role::Pauser, &caller);
+        pause::set_paused(&env, flag, true);
+    }
+
+    /// Unpause an operation (owner or pauser)
+    pub fn unpause(env: Env, caller: Address, flag: PauseFlag) {
+        access_control::require_role(&env, Role::Pauser, &caller);
+        pause::set_paused(&env, flag, false);
+    }
+
+    /// Check if an operation is paused
+    pub fn is_paused(env: Env, flag: PauseFlag) -> bool {
+        pause::is_paused(&env, flag)
     }
 
     /// Create a new identity with IPFS CID
@@ -57,6 +140,7 @@ impl IdentityRegistry {
         ipfs_cid: String,
     ) {
         user.require_auth();
+        pause::require_not_paused(&env, PauseFlag::IdentityCreation);
 
         // Check if identity already exists for this wallet
         if Self::has_identity(env.clone(), user.clone()) {
@@ -79,7 +163,7 @@ impl IdentityRegistry {
         let identity = IdentityRecord {
             ipfs_cid: ipfs_cid.clone(),
             dni: dni.clone(),
-            verification_level: VerificationLevel::Unverified as i32,
+            verification_level: VerificationLevel::Unverified,
             verified: false,
             created_at: timestamp,
         };
@@ -89,13 +173,14 @@ impl IdentityRegistry {
         env.storage()
             .persistent()
             .set(&key, &identity);
+        Self::append_to_index(&env, &user);
         
         // Note: DNI duplicate checking is done by checking all identities
         // This is less efficient but avoids issues with String as storage key
 
         // Increment total users (only if contract is initialized)
         // If not initialized, skip this step to allow creating identities without initialization
-        if env.storage().instance().has(&OWNER) {
+        if access_control::has_owner(&env) {
             let total: i128 = env.storage().instance().get(&TOT_USERS).unwrap_or(0i128);
             env.storage().instance().set(&TOT_USERS, &(total + 1));
         }
@@ -126,27 +211,19 @@ impl IdentityRegistry {
     }
 
 
-    /// Verify a user and assign verification level (only owner)
+    /// Verify a user and assign verification level (owner or verifier)
     pub fn verify_user(
         env: Env,
+        caller: Address,
         user: Address,
-        verification_level: i32,
+        verification_level: VerificationLevel,
     ) {
-        Self::require_owner(&env);
+        access_control::require_role(&env, Role::Verifier, &caller);
 
-        if verification_level < 0 || verification_level > 3 {
-            panic!("Invalid verification level");
-        }
-
-        let key = (symbol_short!("IDENTITY"), user.clone());
-        let mut identity: IdentityRecord = env
-            .storage()
-            .persistent()
-            .get(&key)
-            .expect("Identity not found");
+        let mut identity = Self::get_identity(env.clone(), user.clone());
 
         identity.verification_level = verification_level;
-        identity.verified = verification_level > 0;
+        identity.verified = verification_level != VerificationLevel::Unverified;
 
         let key = (symbol_short!("IDENTITY"), user.clone());
         env.storage()
@@ -163,12 +240,33 @@ impl IdentityRegistry {
     }
 
     /// Get identity record (only CID and verification info)
+    /// Records still in the legacy layout are converted on read
     pub fn get_identity(env: Env, user: Address) -> IdentityRecord {
+        Self::load_identity(&env, &user).expect("Identity not found")
+    }
+
+    /// Read an identity record, accepting both the current and the legacy layout
+    fn load_identity(env: &Env, user: &Address) -> Option<IdentityRecord> {
+        let key = (symbol_short!("IDENTITY"), user.clone());
+        let raw: Val = env.storage().persistent().get(&key)?;
+
+        if let Ok(identity) = IdentityRecord::try_from_val(env, &raw) {
+            return Some(identity);
+        }
+
+        let legacy = LegacyIdentityRecord::try_from_val(env, &raw)
+            .expect("Identity record has an unknown layout");
+        Some(legacy.into())
+    }
+
+    /// Rewrite a legacy identity record in the current layout
+    /// Anyone can call this: every field keeps its value, only the level's encoding changes
+    pub fn migrate_identity(env: Env, user: Address) {
+        let identity = Self::get_identity(env.clone(), user.clone());
         let key = (symbol_short!("IDENTITY"), user);
         env.storage()
             .persistent()
-            .get(&key)
-            .expect("Identity not found")
+            .set(&key, &identity);
     }
 
     /// Get IPFS CID for a user's personal data
@@ -178,11 +276,59 @@ impl IdentityRegistry {
     }
 
     /// Get verification level
-    pub fn get_verification_level(env: Env, user: Address) -> i32 {
+    pub fn get_verification_level(env: Env, user: Address) -> VerificationLevel {
         let identity = Self::get_identity(env, user);
         identity.verification_level
     }
 
+    /// Add a user to the sequential index (no-op if already indexed)
+    fn append_to_index(env: &Env, user: &Address) {
+        let pos_key = (symbol_short!("USR_POS"), user.clone());
+        if env.storage().persistent().has(&pos_key) {
+            return;
+        }
+
+        let count: u32 = env.storage().instance().get(&USR_COUNT).unwrap_or(0);
+        let idx_key = (symbol_short!("USR_IDX"), count);
+        env.storage().persistent().set(&idx_key, user);
+        env.storage().persistent().set(&pos_key, &count);
+        env.storage().instance().set(&USR_COUNT, &(count + 1));
+    }
+
+    /// Index an identity created before the user index existed
+    /// Anyone can call this: it only records an existing identity in the index
+    pub fn index_identity(env: Env, user: Address) {
+        if !Self::has_identity(env.clone(), user.clone()) {
+            panic!("Identity not found");
+        }
+        Self::append_to_index(&env, &user);
+    }
+
+    /// Get number of identities in the user index
+    pub fn get_indexed_count(env: Env) -> u32 {
+        env.storage().instance().get(&USR_COUNT).unwrap_or(0)
+    }
+
+    /// List identities in registration order
+    /// Returns at most `limit` entries (capped at MAX_PAGE_SIZE) starting at index `start`
+    pub fn list_identities(env: Env, start: u32, limit: u32) -> Vec<(Address, IdentityRecord)> {
+        let count = Self::get_indexed_count(env.clone());
+        let end = start.saturating_add(limit.min(MAX_PAGE_SIZE)).min(count);
+
+        let mut page = Vec::new(&env);
+        for index in start..end {
+            let idx_key = (symbol_short!("USR_IDX"), index);
+            let user: Address = env
+                .storage()
+                .persistent()
+                .get(&idx_key)
+                .expect("User index corrupted");
+            let identity = Self::get_identity(env.clone(), user.clone());
+            page.push_back((user, identity));
+        }
+        page
+    }
+
     /// Get total number of registered users
     pub fn get_total_users(env: Env) -> i128 {
         env.storage().instance().get(&TOT_USERS).unwrap_or(0i128)
@@ -207,9 +353,10 @@ impl IdentityRegistry {
 #[cfg(test)]
 mod test {
     use super::*;
-    use soroban_sdk::{String, testutils::Address as _};
+    use soroban_sdk::{String, Vec, testutils::Address as _};
 
     #[test]
+    #[allow(deprecated, clippy::bool_assert_comparison)]
     fn test_identity_registry() {
         let env = Env::default();
         let contract_id = env.register_contract(None, IdentityRegistry);
@@ -231,10 +378,183 @@ mod test {
         client.create_identity(&user, &dni, &ipfs_cid);
         
         assert_eq!(client.has_identity(&user), true);
-        assert_eq!(client.get_verification_level(&user), 0);
+        assert_eq!(client.get_verification_level(&user), VerificationLevel::Unverified);
         assert_eq!(client.is_verified(&user), false);
         assert_eq!(client.get_ipfs_cid(&user), ipfs_cid);
         
         assert_eq!(client.get_total_users(), 1);
+
+        client.verify_user(&owner, &user, &VerificationLevel::Level2);
+        assert_eq!(client.get_verification_level(&user), VerificationLevel::Level2);
+        assert!(client.is_verified(&user));
+
+        // Verification can be delegated to a verifier
+        let verifier = Address::generate(&env);
+        client.grant_role(&owner, &Role::Verifier, &verifier);
+        client.verify_user(&verifier, &user, &VerificationLevel::Level3);
+        assert_eq!(client.get_verification_level(&user), VerificationLevel::Level3);
+
+        let stranger = Address::generate(&env);
+        assert!(client
+            .try_verify_user(&stranger, &user, &VerificationLevel::Level1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_verifier_revoke_and_ownership_transfer() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register(IdentityRegistry, ());
+        let client = IdentityRegistryClient::new(&env, &contract_id);
+        let owner = Address::generate(&env);
+        client.initialize(&owner);
+
+        let user = Address::generate(&env);
+        let dni = String::from_str(&env, "12345678");
+        client.create_identity(&user, &dni, &String::from_str(&env, "QmTest"));
+
+        // A revoked verifier can no longer verify
+        let verifier = Address::generate(&env);
+        client.grant_role(&owner, &Role::Verifier, &verifier);
+        client.verify_user(&verifier, &user, &VerificationLevel::Level1);
+        client.revoke_role(&owner, &Role::Verifier, &verifier);
+        assert!(!client.has_role(&Role::Verifier, &verifier));
+        assert!(client
+            .try_verify_user(&verifier, &user, &VerificationLevel::Level2)
+            .is_err());
+
+        // Admins can grant verifiers, but only the owner grants admins
+        let admin = Address::generate(&env);
+        assert!(client.try_grant_role(&admin, &Role::Verifier, &verifier).is_err());
+        client.grant_role(&owner, &Role::Admin, &admin);
+        client.grant_role(&admin, &Role::Verifier, &verifier);
+        assert!(client.try_grant_role(&admin, &Role::Admin, &verifier).is_err());
+
+        // Ownership moves only once the new owner accepts
+        let new_owner = Address::generate(&env);
+        client.transfer_ownership(&new_owner);
+        assert_eq!(client.get_owner(), owner);
+        client.accept_ownership();
+        assert_eq!(client.get_owner(), new_owner);
+        assert_eq!(client.get_pending_owner(), None);
+        assert!(client
+            .try_verify_user(&owner, &user, &VerificationLevel::Level2)
+            .is_err());
+    }
+
+    #[test]
+    fn test_pause_identity_creation() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register(IdentityRegistry, ());
+        let client = IdentityRegistryClient::new(&env, &contract_id);
+        let owner = Address::generate(&env);
+        client.initialize(&owner);
+
+        let pauser = Address::generate(&env);
+        client.grant_role(&owner, &Role::Pauser, &pauser);
+        client.pause(&pauser, &PauseFlag::IdentityCreation);
+        assert!(client.is_paused(&PauseFlag::IdentityCreation));
+
+        let user = Address::generate(&env);
+        let dni = String::from_str(&env, "12345678");
+        let ipfs_cid = String::from_str(&env, "QmTest");
+        assert!(client.try_create_identity(&user, &dni, &ipfs_cid).is_err());
+
+        // Only the owner or a pauser can toggle flags
+        let stranger = Address::generate(&env);
+        assert!(client.try_unpause(&stranger, &PauseFlag::IdentityCreation).is_err());
+
+        client.unpause(&owner, &PauseFlag::IdentityCreation);
+        client.create_identity(&user, &dni, &ipfs_cid);
+        assert!(client.has_identity(&user));
+    }
+
+    #[test]
+    fn test_list_identities() {
+        let env = Env::default();
+        env.mock_all_auths();
+        let contract_id = env.register(IdentityRegistry, ());
+        let client = IdentityRegistryClient::new(&env, &contract_id);
+        client.initialize(&Address::generate(&env));
+
+        let mut users = Vec::new(&env);
+        for _ in 0..3 {
+            let user = Address::generate(&env);
+            client.create_identity(
+                &user,
+                &String::from_str(&env, "12345678"),
+                &String::from_str(&env, "QmTest"),
+            );
+            users.push_back(user);
+        }
+
+        assert_eq!(client.get_indexed_count(), 3);
+
+        let first_page = client.list_identities(&0, &2);
+        assert_eq!(first_page.len(), 2);
+        assert_eq!(first_page.get(0).unwrap().0, users.get(0).unwrap());
+        assert_eq!(first_page.get(1).unwrap().0, users.get(1).unwrap());
+
+        let second_page = client.list_identities(&2, &2);
+        assert_eq!(second_page.len(), 1);
+        assert_eq!(second_page.get(0).unwrap().0, users.get(2).unwrap());
+
+        assert_eq!(client.list_identities(&5, &2).len(), 0);
+
+        // Re-indexing an identity does not duplicate it
+        client.index_identity(&users.get(0).unwrap());
+        assert_eq!(client.get_indexed_count(), 3);
+    }
+
+    #[test]
+    fn test_legacy_identity_migration() {
+        let env = Env::default();
+        let contract_id = env.register(IdentityRegistry, ());
+        let client = IdentityRegistryClient::new(&env, &contract_id);
+
+        // Write a record the way the contract stored it before the enum change
+        let user = Address::generate(&env);
+        let legacy = LegacyIdentityRecord {
+            ipfs_cid: String::from_str(&env, "QmLegacy"),
+            dni: String::from_str(&env, "87654321"),
+            verification_level: 3,
+            verified: true,
+            created_at: 42,
+        };
+        env.as_contract(&contract_id, || {
+            env.storage()
+                .persistent()
+                .set(&(symbol_short!("IDENTITY"), user.clone()), &legacy);
+        });
+
+        // Legacy records are readable before migration
+        assert_eq!(client.get_verification_level(&user), VerificationLevel::Level3);
+
+        client.migrate_identity(&user);
+
+        let stored: IdentityRecord = env.as_contract(&contract_id, || {
+            env.storage()
+                .persistent()
+                .get(&(symbol_short!("IDENTITY"), user.clone()))
+                .unwrap()
+        });
+        assert_eq!(stored.verification_level, VerificationLevel::Level3);
+        assert!(stored.verified);
+        assert_eq!(stored.created_at, 42);
+
+        // Unknown legacy levels are rejected instead of read as unverified
+        let other = Address::generate(&env);
+        let corrupt = LegacyIdentityRecord {
+            verification_level: 7,
+            ..legacy
+        };
+        env.as_contract(&contract_id, || {
+            env.storage()
+                .persistent()
+                .set(&(symbol_short!("IDENTITY"), other.clone()), &corrupt);
+        });
+        assert!(client.try_get_verification_level(&other).is_err());
+        assert!(client.try_migrate_identity(&other).is_err());
     }
 }
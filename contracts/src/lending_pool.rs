@@ -0,0 +1,914 @@
+use crate::access_control::{self, Role};
+use crate::tranche_token::TrancheShareTokenClient;
+use soroban_sdk::{contract, contractimpl, contracttype, symbol_short, Address, Env, Symbol};
+
+// Risk tranches of the pool.
+// Losses are written down against Junior first; Senior only absorbs what Junior cannot cover.
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Tranche {
+    Senior = 0,
+    Junior = 1,
+}
+
+// Value owned by a tranche and the shares issued against it.
+// Shares are held as the tranche's share token (see set_share_token): minted on deposit,
+// burned on redemption and held by the pool while queued for withdrawal.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[contracttype]
+pub struct TrancheState {
+    pub assets: i128, // deposits plus interest, minus losses (cash, loans and collateral held)
+    pub shares: i128, // share supply of the tranche
+}
+
+// Collateral forfeited to the pool by defaulted loans, held until it is sold
+#[derive(Clone, Debug, Default, PartialEq)]
+#[contracttype]
+pub struct RecoveredCollateral {
+    pub amount: i128, // collateral tokens held
+    pub value: i128,  // book value in pool tokens, counted in the tranche assets
+}
+
+// Shares waiting in the withdrawal queue for cash to redeem them
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct WithdrawalRequest {
+    pub owner: Address,
+    pub tranche: Tranche,
+    pub shares: i128, // still to be redeemed; held by the pool while queued
+}
+
+const TOKEN: Symbol = symbol_short!("TOKEN");
+const LOAN_MANAGER: Symbol = symbol_short!("LOAN_MGR");
+const CASH: Symbol = symbol_short!("CASH"); // Tokens held by the pool
+const LENT: Symbol = symbol_short!("LENT"); // Principal lent out and not yet repaid
+const SENIOR_SHARE: Symbol = symbol_short!("SR_SHARE"); // Senior share of interest, in basis points
+const UNALLOCATED: Symbol = symbol_short!("UNALLOC"); // Interest waiting for depositors
+const QUEUE_HEAD: Symbol = symbol_short!("WQ_HEAD"); // Oldest queue id that may still be pending
+const QUEUE_TAIL: Symbol = symbol_short!("WQ_TAIL"); // Next queue id to assign
+const QUEUE_PENDING: Symbol = symbol_short!("WQ_PEND"); // Number of pending queue entries
+const MAX_FILLS: u32 = 10; // Queue entries visited per fill, to bound the cost of a call
+
+#[contract]
+pub struct LendingPoolMini;
+
+#[contractimpl]
+impl LendingPoolMini {
+    /// Initialize the pool with an owner and the token it lends
+    pub fn initialize(env: Env, owner: Address, token_address: Address) {
+        access_control::init_owner(&env, &owner);
+        env.storage().instance().set(&TOKEN, &token_address);
+    }
+
+    /// Get the owner of the contract
+    pub fn get_owner(env: Env) -> Address {
+        access_control::get_owner(&env)
+    }
+
+    /// Start a two-step ownership transfer (only owner)
+    /// The new owner must call accept_ownership to complete it
+    pub fn transfer_ownership(env: Env, new_owner: Address) {
+        access_control::transfer_ownership(&env, &new_owner);
+    }
+
+    /// Complete a pending ownership transfer (only the pending owner)
+    pub fn accept_ownership(env: Env) {
+        access_control::accept_ownership(&env);
+    }
+
+    /// Get the pending owner of an unfinished ownership transfer
+    pub fn get_pending_owner(env: Env) -> Option<Address> {
+        access_control::get_pending_owner(&env)
+    }
+
+    /// Grant a role (owner, or an admin for non-admin roles)
+    pub fn grant_role(env: Env, caller: Address, role: Role, account: Address) {
+        access_control::grant_role(&env, &caller, role, &account);
+    }
+
+    /// Revoke a role (owner, or an admin for non-admin roles)
+    pub fn revoke_role(env: Env, caller: Address, role: Role, account: Address) {
+        access_control::revoke_role(&env, &caller, role, &account);
+    }
+
+    /// Check if an account was granted a role
+    pub fn has_role(env: Env, role: Role, account: Address) -> bool {
+        access_control::has_role(&env, role, &account)
+    }
+
+    /// Set the loan manager allowed to draw and repay pool funds (only owner)
+    pub fn set_loan_manager(env: Env, loan_manager: Address) {
+        access_control::require_owner(&env);
+        env.storage().instance().set(&LOAN_MANAGER, &loan_manager);
+    }
+
+    /// Set the share of interest paid to the senior tranche (owner or risk manager)
+    /// The junior tranche receives the rest.
+    pub fn set_senior_interest_share(env: Env, caller: Address, share_bps: i128) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+
+        if !(0..=10000).contains(&share_bps) {
+            panic!("Share must be between 0 and 10000 basis points");
+        }
+
+        env.storage().instance().set(&SENIOR_SHARE, &share_bps);
+    }
+
+    /// Set the SEP-41 token that holds the shares of a tranche (only owner)
+    /// The token must be minted by this pool, and the tranche must have no shares yet.
+    pub fn set_share_token(env: Env, tranche: Tranche, share_token: Address) {
+        access_control::require_owner(&env);
+
+        if Self::get_tranche(env.clone(), tranche).shares != 0 {
+            panic!("Tranche already has shares");
+        }
+        let client = TrancheShareTokenClient::new(&env, &share_token);
+        if client.get_pool() != env.current_contract_address() {
+            panic!("Share token is not minted by this pool");
+        }
+
+        env.storage()
+            .instance()
+            .set(&(symbol_short!("SHR_TKN"), tranche), &share_token);
+    }
+
+    /// Get the share token of a tranche, if set
+    pub fn get_share_token(env: Env, tranche: Tranche) -> Option<Address> {
+        env.storage()
+            .instance()
+            .get(&(symbol_short!("SHR_TKN"), tranche))
+    }
+
+    /// Get the share of interest paid to the senior tranche (default 70%)
+    pub fn get_senior_interest_share(env: Env) -> i128 {
+        env.storage().instance().get(&SENIOR_SHARE).unwrap_or(7000)
+    }
+
+    /// Deposit funds into a tranche and receive its shares
+    /// Returns the shares minted.
+    pub fn deposit(env: Env, user: Address, tranche: Tranche, amount: i128) -> i128 {
+        user.require_auth();
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        let mut state = Self::get_tranche(env.clone(), tranche);
+        let shares = if state.shares == 0 {
+            amount
+        } else if state.assets == 0 {
+            panic!("Tranche has been wiped out by losses");
+        } else {
+            amount * state.shares / state.assets
+        };
+        if shares == 0 {
+            panic!("Deposit too small");
+        }
+
+        let share_token = Self::share_token(&env, tranche);
+        let token_client = soroban_sdk::token::Client::new(&env, &Self::get_token_address(&env));
+        token_client.transfer(&user, env.current_contract_address(), &amount);
+
+        state.assets += amount;
+        state.shares += shares;
+        Self::set_tranche(&env, tranche, &state);
+        Self::set_cash(&env, Self::get_total_liquidity(env.clone()) + amount);
+        share_token.mint(&user, &shares);
+
+        Self::fill_withdrawals(&env);
+
+        shares
+    }
+
+    /// Redeem tranche shares for their current value
+    /// Only cash that is not lent out can be withdrawn. The withdrawal queue is served first,
+    /// and cash still needed for shares of the same tranche waiting in the queue is kept for
+    /// them. Returns the amount paid out.
+    pub fn withdraw(env: Env, user: Address, tranche: Tranche, shares: i128) -> i128 {
+        user.require_auth();
+
+        if shares <= 0 {
+            panic!("Shares must be positive");
+        }
+
+        let balance = Self::get_balance(env.clone(), tranche, user.clone());
+        if shares > balance {
+            panic!("Insufficient shares");
+        }
+
+        Self::fill_withdrawals(&env);
+
+        let mut state = Self::get_tranche(env.clone(), tranche);
+        let amount = shares * state.assets / state.shares;
+        let queued = Self::get_queued_shares(env.clone(), tranche) * state.assets / state.shares;
+
+        let cash = Self::get_total_liquidity(env.clone());
+        if amount > cash - queued {
+            panic!("Insufficient liquidity");
+        }
+
+        state.assets -= amount;
+        state.shares -= shares;
+        Self::set_tranche(&env, tranche, &state);
+        Self::set_cash(&env, cash - amount);
+        let share_token = Self::share_token(&env, tranche);
+        share_token.transfer(&user, env.current_contract_address(), &shares);
+        share_token.burn(&env.current_contract_address(), &shares);
+
+        let token_client = soroban_sdk::token::Client::new(&env, &Self::get_token_address(&env));
+        token_client.transfer(&env.current_contract_address(), &user, &amount);
+
+        amount
+    }
+
+    /// Get the tranche shares held by a user (their balance of the tranche share token)
+    pub fn get_balance(env: Env, tranche: Tranche, user: Address) -> i128 {
+        match Self::get_share_token(env.clone(), tranche) {
+            Some(share_token) => TrancheShareTokenClient::new(&env, &share_token).balance(&user),
+            None => 0,
+        }
+    }
+
+    /// Get the current value of a user's tranche shares
+    pub fn get_position_value(env: Env, tranche: Tranche, user: Address) -> i128 {
+        let state = Self::get_tranche(env.clone(), tranche);
+        if state.shares == 0 {
+            return 0;
+        }
+        Self::get_balance(env, tranche, user) * state.assets / state.shares
+    }
+
+    /// Get the assets and share supply of a tranche
+    pub fn get_tranche(env: Env, tranche: Tranche) -> TrancheState {
+        env.storage()
+            .instance()
+            .get(&(symbol_short!("TRANCHE"), tranche))
+            .unwrap_or_default()
+    }
+
+    /// Get the cash available to lend or withdraw
+    pub fn get_total_liquidity(env: Env) -> i128 {
+        env.storage().instance().get(&CASH).unwrap_or(0)
+    }
+
+    /// Get the principal currently lent out
+    pub fn get_lent_out(env: Env) -> i128 {
+        env.storage().instance().get(&LENT).unwrap_or(0)
+    }
+
+    /// Send loan principal to a borrower (only loan manager)
+    pub fn disburse_loan(env: Env, borrower: Address, amount: i128) {
+        Self::require_loan_manager(&env);
+
+        let cash = Self::get_total_liquidity(env.clone());
+        if amount <= 0 || amount > cash {
+            panic!("Insufficient liquidity");
+        }
+
+        Self::set_cash(&env, cash - amount);
+        env.storage()
+            .instance()
+            .set(&LENT, &(Self::get_lent_out(env.clone()) + amount));
+
+        let token_client = soroban_sdk::token::Client::new(&env, &Self::get_token_address(&env));
+        token_client.transfer(&env.current_contract_address(), &borrower, &amount);
+    }
+
+    /// Record a repayment already transferred to the pool (only loan manager)
+    /// The interest is split between the tranches; a tranche without depositors
+    /// passes its share to the other one, and with no depositors at all it waits for the
+    /// next split.
+    pub fn register_repayment(env: Env, principal: i128, interest: i128) {
+        Self::require_loan_manager(&env);
+
+        if principal < 0 || interest < 0 {
+            panic!("Amounts cannot be negative");
+        }
+
+        Self::set_cash(&env, Self::get_total_liquidity(env.clone()) + principal + interest);
+        env.storage()
+            .instance()
+            .set(&LENT, &(Self::get_lent_out(env.clone()) - principal));
+
+        Self::credit_interest(&env, interest);
+        Self::fill_withdrawals(&env);
+    }
+
+    /// Write off principal that will not be repaid (only loan manager)
+    /// The loss is absorbed by the junior tranche first, then by the senior tranche.
+    pub fn register_loss(env: Env, principal: i128) {
+        Self::require_loan_manager(&env);
+
+        if principal < 0 {
+            panic!("Amount cannot be negative");
+        }
+
+        env.storage()
+            .instance()
+            .set(&LENT, &(Self::get_lent_out(env.clone()) - principal));
+        Self::absorb_loss(&env, principal);
+    }
+
+    /// Record collateral of a written-off loan already transferred to the pool
+    /// (only loan manager)
+    /// `value` of lent-out principal is now held as `amount` of the collateral token, so it
+    /// stays in the tranche assets instead of being written off.
+    pub fn register_recovery(env: Env, token: Address, amount: i128, value: i128) {
+        Self::require_loan_manager(&env);
+
+        if amount < 0 || value < 0 {
+            panic!("Amounts cannot be negative");
+        }
+
+        env.storage()
+            .instance()
+            .set(&LENT, &(Self::get_lent_out(env.clone()) - value));
+
+        let mut recovered = Self::get_recovered(env.clone(), token.clone());
+        recovered.amount += amount;
+        recovered.value += value;
+        Self::set_recovered(&env, &token, &recovered);
+    }
+
+    /// Sell recovered collateral to `buyer` for `price` pool tokens (owner or risk manager,
+    /// with the buyer's consent)
+    /// The difference with the book value is a gain split like interest or a loss absorbed
+    /// junior first.
+    pub fn sell_recovered(
+        env: Env,
+        caller: Address,
+        buyer: Address,
+        token: Address,
+        amount: i128,
+        price: i128,
+    ) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+        buyer.require_auth();
+
+        let mut recovered = Self::get_recovered(env.clone(), token.clone());
+        if amount <= 0 || amount > recovered.amount {
+            panic!("Invalid collateral amount");
+        }
+        if price < 0 {
+            panic!("Price cannot be negative");
+        }
+
+        let book = if amount == recovered.amount {
+            recovered.value
+        } else {
+            recovered.value * amount / recovered.amount
+        };
+        recovered.amount -= amount;
+        recovered.value -= book;
+        Self::set_recovered(&env, &token, &recovered);
+
+        let pool_token = soroban_sdk::token::Client::new(&env, &Self::get_token_address(&env));
+        pool_token.transfer(&buyer, env.current_contract_address(), &price);
+        soroban_sdk::token::Client::new(&env, &token).transfer(
+            &env.current_contract_address(),
+            &buyer,
+            &amount,
+        );
+
+        Self::set_cash(&env, Self::get_total_liquidity(env.clone()) + price);
+        if price >= book {
+            Self::credit_interest(&env, price - book);
+        } else {
+            Self::absorb_loss(&env, book - price);
+        }
+
+        Self::fill_withdrawals(&env);
+    }
+
+    /// Get the collateral of a token the pool holds from written-off loans
+    pub fn get_recovered(env: Env, token: Address) -> RecoveredCollateral {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("RECOV"), token))
+            .unwrap_or_default()
+    }
+
+    /// Get interest held back because neither tranche had depositors when it arrived
+    pub fn get_unallocated_interest(env: Env) -> i128 {
+        env.storage().instance().get(&UNALLOCATED).unwrap_or(0)
+    }
+
+    /// Split interest between the tranches; a tranche without depositors passes its share
+    /// to the other one. With no depositors at all the interest is held back and added to
+    /// the next split.
+    fn credit_interest(env: &Env, interest: i128) {
+        let mut senior = Self::get_tranche(env.clone(), Tranche::Senior);
+        let mut junior = Self::get_tranche(env.clone(), Tranche::Junior);
+
+        let interest = interest + Self::get_unallocated_interest(env.clone());
+        if senior.shares == 0 && junior.shares == 0 {
+            env.storage().instance().set(&UNALLOCATED, &interest);
+            return;
+        }
+        env.storage().instance().remove(&UNALLOCATED);
+
+        let senior_interest = if senior.shares == 0 {
+            0
+        } else if junior.shares == 0 {
+            interest
+        } else {
+            interest * Self::get_senior_interest_share(env.clone()) / 10000
+        };
+        senior.assets += senior_interest;
+        junior.assets += interest - senior_interest;
+
+        Self::set_tranche(env, Tranche::Senior, &senior);
+        Self::set_tranche(env, Tranche::Junior, &junior);
+    }
+
+    /// Write a loss down against the junior tranche first, then the senior tranche
+    fn absorb_loss(env: &Env, loss: i128) {
+        let mut junior = Self::get_tranche(env.clone(), Tranche::Junior);
+        let junior_loss = loss.min(junior.assets);
+        junior.assets -= junior_loss;
+        Self::set_tranche(env, Tranche::Junior, &junior);
+
+        let mut senior = Self::get_tranche(env.clone(), Tranche::Senior);
+        senior.assets -= (loss - junior_loss).min(senior.assets);
+        Self::set_tranche(env, Tranche::Senior, &senior);
+    }
+
+    /// Queue shares for redemption when cash comes back to the pool
+    /// The shares move to the pool and are redeemed first-in first-out, possibly over
+    /// several fills, at the tranche value at the time of each fill. Returns the queue id.
+    pub fn request_withdrawal(env: Env, user: Address, tranche: Tranche, shares: i128) -> u32 {
+        user.require_auth();
+
+        if shares <= 0 {
+            panic!("Shares must be positive");
+        }
+
+        let balance = Self::get_balance(env.clone(), tranche, user.clone());
+        if shares > balance {
+            panic!("Insufficient shares");
+        }
+        Self::share_token(&env, tranche).transfer(&user, env.current_contract_address(), &shares);
+        Self::add_queued_shares(&env, tranche, shares);
+
+        let request_id: u32 = env.storage().instance().get(&QUEUE_TAIL).unwrap_or(0);
+        env.storage().instance().set(&QUEUE_TAIL, &(request_id + 1));
+        let pending = Self::get_queue_length(env.clone());
+        env.storage().instance().set(&QUEUE_PENDING, &(pending + 1));
+
+        let request = WithdrawalRequest {
+            owner: user,
+            tranche,
+            shares,
+        };
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("WQ"), request_id), &request);
+
+        Self::fill_withdrawals(&env);
+
+        request_id
+    }
+
+    /// Leave the withdrawal queue and get back the shares not yet redeemed
+    pub fn cancel_withdrawal(env: Env, user: Address, request_id: u32) {
+        user.require_auth();
+
+        let request = Self::get_withdrawal(env.clone(), request_id).expect("Request not found");
+        if request.owner != user {
+            panic!("Not your request");
+        }
+
+        Self::remove_withdrawal(&env, request_id);
+        Self::add_queued_shares(&env, request.tranche, -request.shares);
+        Self::share_token(&env, request.tranche).transfer(
+            &env.current_contract_address(),
+            &user,
+            &request.shares,
+        );
+    }
+
+    /// Redeem queued withdrawals with the cash currently held
+    /// Repayments and deposits already do this; anyone can call it to continue a fill
+    /// that stopped at MAX_FILLS entries.
+    pub fn process_withdrawals(env: Env) {
+        Self::fill_withdrawals(&env);
+    }
+
+    /// Get a pending withdrawal request
+    pub fn get_withdrawal(env: Env, request_id: u32) -> Option<WithdrawalRequest> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("WQ"), request_id))
+    }
+
+    /// Get the number of pending requests ahead of a request in the queue
+    pub fn get_queue_position(env: Env, request_id: u32) -> u32 {
+        if Self::get_withdrawal(env.clone(), request_id).is_none() {
+            panic!("Request not found");
+        }
+
+        let head: u32 = env.storage().instance().get(&QUEUE_HEAD).unwrap_or(0);
+        let mut ahead = 0;
+        for id in head..request_id {
+            if Self::get_withdrawal(env.clone(), id).is_some() {
+                ahead += 1;
+            }
+        }
+        ahead
+    }
+
+    /// Get the number of pending withdrawal requests
+    pub fn get_queue_length(env: Env) -> u32 {
+        env.storage().instance().get(&QUEUE_PENDING).unwrap_or(0)
+    }
+
+    /// Get the shares of a tranche waiting in the withdrawal queue
+    pub fn get_queued_shares(env: Env, tranche: Tranche) -> i128 {
+        env.storage()
+            .instance()
+            .get(&(symbol_short!("WQ_SHARES"), tranche))
+            .unwrap_or(0)
+    }
+
+    /// Pay queued withdrawals in order until cash runs out or MAX_FILLS entries were visited
+    fn fill_withdrawals(env: &Env) {
+        let tail: u32 = env.storage().instance().get(&QUEUE_TAIL).unwrap_or(0);
+        let mut head: u32 = env.storage().instance().get(&QUEUE_HEAD).unwrap_or(0);
+        let token_client = soroban_sdk::token::Client::new(env, &Self::get_token_address(env));
+
+        let mut visited = 0;
+        while head < tail && visited < MAX_FILLS {
+            visited += 1;
+
+            // Cancelled entries leave gaps
+            let Some(mut request) = Self::get_withdrawal(env.clone(), head) else {
+                head += 1;
+                continue;
+            };
+
+            let cash = Self::get_total_liquidity(env.clone());
+            let mut state = Self::get_tranche(env.clone(), request.tranche);
+            let value = request.shares * state.assets / state.shares;
+
+            // Redeem as many shares as the cash covers
+            let (shares, amount) = if value <= cash {
+                (request.shares, value)
+            } else {
+                let shares = cash * state.shares / state.assets;
+                (shares, shares * state.assets / state.shares)
+            };
+            if shares == 0 {
+                break;
+            }
+
+            state.assets -= amount;
+            state.shares -= shares;
+            Self::set_tranche(env, request.tranche, &state);
+            Self::set_cash(env, cash - amount);
+            Self::add_queued_shares(env, request.tranche, -shares);
+            Self::share_token(env, request.tranche).burn(&env.current_contract_address(), &shares);
+            token_client.transfer(&env.current_contract_address(), &request.owner, &amount);
+
+            if shares < request.shares {
+                request.shares -= shares;
+                env.storage()
+                    .persistent()
+                    .set(&(symbol_short!("WQ"), head), &request);
+                break;
+            }
+
+            Self::remove_withdrawal(env, head);
+            head += 1;
+        }
+
+        env.storage().instance().set(&QUEUE_HEAD, &head);
+    }
+
+    /// Delete a queue entry and update the pending count
+    fn remove_withdrawal(env: &Env, request_id: u32) {
+        env.storage()
+            .persistent()
+            .remove(&(symbol_short!("WQ"), request_id));
+        let pending = Self::get_queue_length(env.clone());
+        env.storage().instance().set(&QUEUE_PENDING, &(pending - 1));
+    }
+
+    /// Require auth from the configured loan manager
+    fn require_loan_manager(env: &Env) {
+        let loan_manager: Address = env
+            .storage()
+            .instance()
+            .get(&LOAN_MANAGER)
+            .expect("Loan manager not set");
+        loan_manager.require_auth();
+    }
+
+    /// Get token address
+    fn get_token_address(env: &Env) -> Address {
+        env.storage()
+            .instance()
+            .get(&TOKEN)
+            .expect("Token not initialized")
+    }
+
+    fn set_cash(env: &Env, cash: i128) {
+        env.storage().instance().set(&CASH, &cash);
+    }
+
+    fn set_recovered(env: &Env, token: &Address, recovered: &RecoveredCollateral) {
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("RECOV"), token.clone()), recovered);
+    }
+
+    fn add_queued_shares(env: &Env, tranche: Tranche, delta: i128) {
+        let queued = Self::get_queued_shares(env.clone(), tranche) + delta;
+        env.storage()
+            .instance()
+            .set(&(symbol_short!("WQ_SHARES"), tranche), &queued);
+    }
+
+    fn set_tranche(env: &Env, tranche: Tranche, state: &TrancheState) {
+        env.storage()
+            .instance()
+            .set(&(symbol_short!("TRANCHE"), tranche), state);
+    }
+
+    /// Get the share token of a tranche, which must be set
+    fn share_token(env: &Env, tranche: Tranche) -> TrancheShareTokenClient<'_> {
+        let share_token = Self::get_share_token(env.clone(), tranche).expect("Share token not set");
+        TrancheShareTokenClient::new(env, &share_token)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock_usdc::{MockUSDC, MockUSDCClient};
+    use crate::tranche_token::TrancheShareToken;
+    use soroban_sdk::{testutils::Address as _, token, String};
+
+    struct Setup {
+        env: Env,
+        token: MockUSDCClient<'static>,
+        pool: LendingPoolMiniClient<'static>,
+    }
+
+    /// Register a share token for each tranche of a pool
+    fn register_share_tokens(env: &Env, pool: &LendingPoolMiniClient) {
+        for (tranche, symbol) in [(Tranche::Senior, "lpSR"), (Tranche::Junior, "lpJR")] {
+            let share_token = env.register(TrancheShareToken, ());
+            TrancheShareTokenClient::new(env, &share_token).initialize(
+                &pool.address,
+                &String::from_str(env, symbol),
+                &String::from_str(env, symbol),
+                &6u32,
+            );
+            pool.set_share_token(&tranche, &share_token);
+        }
+    }
+
+    /// Register a token and a pool lending it, with share tokens and a loan manager set
+    fn setup() -> Setup {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        let admin = Address::generate(&env);
+        let token_id = env.register(MockUSDC, ());
+        let token = MockUSDCClient::new(&env, &token_id);
+        token.initialize(
+            &admin,
+            &String::from_str(&env, "Mock USDC"),
+            &String::from_str(&env, "USDC"),
+            &6u32,
+        );
+
+        let pool_id = env.register(LendingPoolMini, ());
+        let pool = LendingPoolMiniClient::new(&env, &pool_id);
+        pool.initialize(&admin, &token_id);
+        register_share_tokens(&env, &pool);
+        pool.set_loan_manager(&Address::generate(&env));
+
+        Setup { env, token, pool }
+    }
+
+    #[test]
+    fn test_tranche_interest_and_losses() {
+        let Setup { env, token, pool } = setup();
+        let pool_id = pool.address.clone();
+
+        let senior_lender = Address::generate(&env);
+        let junior_lender = Address::generate(&env);
+        token.mint(&senior_lender, &800);
+        token.mint(&junior_lender, &200);
+        assert_eq!(pool.deposit(&senior_lender, &Tranche::Senior, &800), 800);
+        assert_eq!(pool.deposit(&junior_lender, &Tranche::Junior, &200), 200);
+
+        // 100 of interest is split 70/30
+        let borrower = Address::generate(&env);
+        pool.disburse_loan(&borrower, &500);
+        assert_eq!(pool.get_lent_out(), 500);
+        token.transfer(&borrower, &pool_id, &100);
+        pool.register_repayment(&0, &100);
+        assert_eq!(pool.get_position_value(&Tranche::Senior, &senior_lender), 870);
+        assert_eq!(pool.get_position_value(&Tranche::Junior, &junior_lender), 230);
+
+        // A loss of 300 wipes out the junior tranche before touching the senior one
+        pool.register_loss(&300);
+        assert_eq!(pool.get_tranche(&Tranche::Junior).assets, 0);
+        assert_eq!(pool.get_tranche(&Tranche::Senior).assets, 800);
+        assert!(pool.try_deposit(&junior_lender, &Tranche::Junior, &1).is_err());
+
+        // Withdrawals are limited to cash that is not lent out
+        assert_eq!(pool.get_total_liquidity(), 600);
+        assert!(pool.try_withdraw(&senior_lender, &Tranche::Senior, &800).is_err());
+        assert_eq!(pool.withdraw(&senior_lender, &Tranche::Senior, &600), 600);
+        assert_eq!(token.balance(&senior_lender), 600);
+    }
+
+    #[test]
+    fn test_tranche_shares_are_separate() {
+        let Setup { env, token, pool } = setup();
+
+        let lender = Address::generate(&env);
+        let other = Address::generate(&env);
+        token.mint(&lender, &100);
+        pool.deposit(&lender, &Tranche::Junior, &100);
+
+        assert_eq!(pool.get_balance(&Tranche::Junior, &lender), 100);
+        assert_eq!(pool.get_balance(&Tranche::Senior, &lender), 0);
+        assert!(pool
+            .try_withdraw(&lender, &Tranche::Senior, &100)
+            .is_err());
+
+        // Shares are a SEP-41 token: they can be transferred and redeemed by the recipient
+        let junior_shares = token::Client::new(
+            &env,
+            &pool.get_share_token(&Tranche::Junior).unwrap(),
+        );
+        assert_eq!(junior_shares.balance(&lender), 100);
+        junior_shares.transfer(&lender, &other, &40);
+        assert_eq!(pool.get_balance(&Tranche::Junior, &other), 40);
+        assert_eq!(pool.withdraw(&other, &Tranche::Junior, &40), 40);
+        assert_eq!(junior_shares.balance(&other), 0);
+        assert_eq!(pool.get_tranche(&Tranche::Junior).shares, 60);
+        assert_eq!(
+            TrancheShareTokenClient::new(&env, &junior_shares.address).total_supply(),
+            60
+        );
+
+        // Only the pool burns shares, when it redeems them
+        assert!(junior_shares.try_burn(&lender, &10).is_err());
+
+        // A tranche with shares keeps its share token, and a token of another pool is refused
+        let share_token = env.register(TrancheShareToken, ());
+        TrancheShareTokenClient::new(&env, &share_token).initialize(
+            &Address::generate(&env),
+            &String::from_str(&env, "lpJR"),
+            &String::from_str(&env, "lpJR"),
+            &6u32,
+        );
+        assert!(pool.try_set_share_token(&Tranche::Junior, &share_token).is_err());
+        let other_pool = env.register(LendingPoolMini, ());
+        let other_pool = LendingPoolMiniClient::new(&env, &other_pool);
+        other_pool.initialize(&lender, &token.address);
+        assert!(other_pool
+            .try_set_share_token(&Tranche::Senior, &share_token)
+            .is_err());
+
+        // Only the loan manager can move pool funds
+        env.set_auths(&[]);
+        assert!(pool.try_disburse_loan(&other, &10).is_err());
+    }
+
+    #[test]
+    fn test_withdrawal_queue_fills_in_order() {
+        let Setup { env, token, pool } = setup();
+        let pool_id = pool.address.clone();
+
+        let first = Address::generate(&env);
+        let second = Address::generate(&env);
+        token.mint(&first, &500);
+        token.mint(&second, &500);
+        pool.deposit(&first, &Tranche::Senior, &500);
+        pool.deposit(&second, &Tranche::Senior, &500);
+
+        // Everything is lent out, so withdrawals have to queue
+        let borrower = Address::generate(&env);
+        pool.disburse_loan(&borrower, &1_000);
+        let first_id = pool.request_withdrawal(&first, &Tranche::Senior, &400);
+        let second_id = pool.request_withdrawal(&second, &Tranche::Senior, &300);
+        assert_eq!(pool.get_queue_position(&first_id), 0);
+        assert_eq!(pool.get_queue_position(&second_id), 1);
+        assert_eq!(pool.get_balance(&Tranche::Senior, &first), 100);
+        assert_eq!(pool.get_balance(&Tranche::Senior, &pool.address), 700);
+
+        // Nobody can skip the queue with a direct withdrawal
+        token.mint(&first, &100);
+        pool.deposit(&first, &Tranche::Senior, &100);
+        assert_eq!(token.balance(&first), 100);
+        assert!(pool.try_withdraw(&second, &Tranche::Senior, &100).is_err());
+
+        // A repayment fills the oldest request first, partially if needed
+        token.transfer(&borrower, &pool_id, &500);
+        pool.register_repayment(&500, &0);
+        assert_eq!(token.balance(&first), 400);
+        assert_eq!(pool.get_withdrawal(&first_id), None);
+        assert_eq!(token.balance(&second), 200);
+        assert_eq!(pool.get_withdrawal(&second_id).unwrap().shares, 100);
+        assert_eq!(pool.get_queue_position(&second_id), 0);
+
+        // Cancelling returns the shares that were not redeemed yet
+        pool.cancel_withdrawal(&second, &second_id);
+        assert_eq!(pool.get_balance(&Tranche::Senior, &second), 300);
+        assert_eq!(pool.get_queue_length(), 0);
+        assert!(pool.try_cancel_withdrawal(&second, &second_id).is_err());
+    }
+
+    #[test]
+    fn test_interest_goes_to_tranches_with_depositors() {
+        let Setup { env, token, pool } = setup();
+        let pool_id = pool.address.clone();
+        let borrower = Address::generate(&env);
+        token.mint(&borrower, &1_000);
+
+        // Interest with no depositors at all waits for the next split
+        token.transfer(&borrower, &pool_id, &50);
+        pool.register_repayment(&0, &50);
+        assert_eq!(pool.get_unallocated_interest(), 50);
+        assert_eq!(pool.get_tranche(&Tranche::Senior).assets, 0);
+        assert_eq!(pool.get_tranche(&Tranche::Junior).assets, 0);
+
+        // A junior-only pool gets all of it, including what was held back
+        let junior_lender = Address::generate(&env);
+        token.mint(&junior_lender, &100);
+        pool.deposit(&junior_lender, &Tranche::Junior, &100);
+        assert_eq!(pool.get_position_value(&Tranche::Junior, &junior_lender), 100);
+        token.transfer(&borrower, &pool_id, &10);
+        pool.register_repayment(&0, &10);
+        assert_eq!(pool.get_unallocated_interest(), 0);
+        assert_eq!(pool.get_position_value(&Tranche::Junior, &junior_lender), 160);
+        assert_eq!(pool.get_tranche(&Tranche::Senior).assets, 0);
+    }
+
+    #[test]
+    fn test_withdraw_only_waits_for_its_tranche_queue() {
+        let Setup { env, token, pool } = setup();
+        let pool_id = pool.address.clone();
+
+        let senior_lender = Address::generate(&env);
+        let junior_lender = Address::generate(&env);
+        token.mint(&senior_lender, &500);
+        token.mint(&junior_lender, &100);
+        pool.deposit(&senior_lender, &Tranche::Senior, &500);
+        pool.deposit(&junior_lender, &Tranche::Junior, &100);
+
+        // With everything lent out, the senior lender queues 22 small requests
+        let borrower = Address::generate(&env);
+        pool.disburse_loan(&borrower, &600);
+        for _ in 0..22 {
+            pool.request_withdrawal(&senior_lender, &Tranche::Senior, &10);
+        }
+        assert_eq!(pool.get_queued_shares(&Tranche::Senior), 220);
+
+        // A fill visits at most 10 requests, oldest first
+        token.transfer(&borrower, &pool_id, &300);
+        pool.register_repayment(&300, &0);
+        assert_eq!(token.balance(&senior_lender), 100);
+        assert_eq!(pool.get_withdrawal(&9), None);
+        assert_eq!(pool.get_queue_position(&10), 0);
+        assert_eq!(pool.get_queue_length(), 12);
+        assert_eq!(pool.get_total_liquidity(), 200);
+
+        // A withdrawal serves the queue first and leaves enough cash for what is still
+        // queued in its tranche
+        assert!(pool
+            .try_withdraw(&senior_lender, &Tranche::Senior, &90)
+            .is_err());
+
+        // The junior tranche has nobody queued, so it is not held back by senior requests
+        assert_eq!(pool.withdraw(&junior_lender, &Tranche::Junior, &50), 50);
+        assert_eq!(token.balance(&senior_lender), 200);
+        assert_eq!(pool.get_queue_length(), 2);
+        assert_eq!(pool.get_queued_shares(&Tranche::Senior), 20);
+        assert_eq!(pool.get_total_liquidity(), 50);
+
+        assert_eq!(pool.withdraw(&senior_lender, &Tranche::Senior, &30), 30);
+        assert_eq!(token.balance(&senior_lender), 250);
+        assert_eq!(pool.get_queue_length(), 0);
+        assert_eq!(pool.get_queued_shares(&Tranche::Senior), 0);
+
+        // A request larger than the cash is filled in parts
+        let request_id = pool.request_withdrawal(&senior_lender, &Tranche::Senior, &100);
+        assert_eq!(pool.get_withdrawal(&request_id).unwrap().shares, 100);
+        token.transfer(&borrower, &pool_id, &60);
+        pool.register_repayment(&60, &0);
+        assert_eq!(pool.get_withdrawal(&request_id).unwrap().shares, 40);
+        assert_eq!(pool.get_queued_shares(&Tranche::Senior), 40);
+        token.transfer(&borrower, &pool_id, &40);
+        pool.register_repayment(&40, &0);
+        assert_eq!(pool.get_withdrawal(&request_id), None);
+        assert_eq!(token.balance(&senior_lender), 350);
+    }
+}
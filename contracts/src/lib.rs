@@ -1,5 +1,11 @@
 #![no_std]
+pub mod access_control;
 pub mod identity_registry;
 pub mod credit_scoring;
+pub mod lending_pool;
+pub mod tranche_token;
 pub mod mock_usdc;
+pub mod mock_oracle;
+pub mod oracle;
 pub mod loan_manager;
+pub mod pause;
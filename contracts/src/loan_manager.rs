@@ -1,5 +1,11 @@
+use crate::access_control::{self, Role};
+use crate::identity_registry::VerificationLevel;
+use crate::lending_pool::LendingPoolMiniClient;
+use crate::oracle::{Asset, PriceOracleClient};
+use crate::pause::{self, PauseFlag};
 use soroban_sdk::{
-    contract, contractimpl, contracttype, symbol_short, Address, Env, Symbol, IntoVal,
+    contract, contractimpl, contracttype, symbol_short, Address, Env, String, Symbol, IntoVal,
+    Map, TryFromVal, Val, Vec,
 };
 
 #[derive(Clone)]
@@ -13,20 +19,316 @@ pub struct Loan {
     pub paid_payments: u32,
     pub payment_amount: i128,
     pub created_at: i128, // Changed from u64 to i128 for Soroban compatibility
-    pub status: i32, // Changed from LoanStatus enum to i32 for Soroban compatibility
+    pub status: LoanStatus,
 }
 
-#[derive(Clone, Copy, PartialEq)]
-#[repr(u8)]
+// Running portfolio aggregates, updated on every loan state transition
+#[derive(Clone, Debug, Default, PartialEq)]
+#[contracttype]
+pub struct PortfolioStats {
+    pub total_disbursed: i128,       // principal ever lent out
+    pub total_repaid: i128,          // installments ever received (principal + interest)
+    pub outstanding_principal: i128, // principal still owed on active loans
+    pub interest_earned: i128,       // interest portion of received installments
+    pub active_count: u32,
+    pub repaid_count: u32,
+    pub defaulted_count: u32,
+}
+
+// Collateral accepted by secured loans
+// Valued through the price oracle, or at par with the loan token until one is configured
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct CollateralConfig {
+    pub token: Address,
+    pub ltv_bps: i128, // share of the collateral value that can be borrowed, in basis points
+}
+
+// Collateral locked in escrow for one secured loan, stored by loan id
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct CollateralPosition {
+    pub token: Address,
+    pub amount: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum OfferStatus {
+    Open = 0,
+    Filled = 1,
+    Cancelled = 2,
+}
+
+// Loan terms posted by a P2P lender, with the borrower requirements to take them
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct LoanOffer {
+    pub lender: Address,
+    pub amount: i128,        // principal, escrowed while the offer is open
+    pub interest_rate: i128, // in basis points
+    pub num_payments: u32,
+    pub min_score: i128,
+    pub min_level: VerificationLevel,
+    pub status: OfferStatus,
+    pub loan_id: Option<u32>, // loan created when the offer was accepted
+}
+
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum RequestStatus {
+    Open = 0,
+    Disbursed = 1,
+    Cancelled = 2,
+}
+
+// Loan request posted by a borrower, funded pro-rata by one or more lenders
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct LoanRequest {
+    pub borrower: Address,
+    pub amount: i128,   // principal asked for
+    pub max_rate: i128, // in basis points; the loan is priced at this rate
+    pub num_payments: u32,
+    pub purpose_cid: String, // IPFS CID describing what the loan is for
+    pub deadline: u64,       // ledger timestamp after which lenders can reclaim
+    pub funded: i128,        // principal committed by lenders so far
+    pub status: RequestStatus,
+    pub loan_id: Option<u32>, // pending loan, disbursed once fully funded
+}
+
+// Parameters for liquidating under-collateralized secured loans
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct LiquidationConfig {
+    pub threshold_bps: i128,    // share of the collateral value counted toward the health factor
+    pub close_factor_bps: i128, // share of the remaining installments one liquidation may repay
+    pub bonus_bps: i128,        // extra collateral value paid to the liquidator
+    pub penalty: i128,          // score points removed from the borrower per liquidation
+}
+
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ModificationKind {
+    Restructure = 0,
+    Forbearance = 1,
+}
+
+// One change to the terms of a loan, kept in the loan's modification history
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct LoanModification {
+    pub kind: ModificationKind,
+    pub approved_by: Address,
+    pub modified_at: u64,
+    pub debt_before: i128, // installments owed before the change
+    pub debt_after: i128,  // installments owed after the change
+    pub num_payments: u32, // installments remaining after the change
+    pub interest_rate: i128,
+    pub periods: u32, // payment periods deferred (forbearance only)
+}
+
+// Protocol fees, in basis points
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct FeeConfig {
+    pub origination_fee_bps: i128, // of the principal, deducted at disbursement
+    pub protocol_fee_bps: i128,    // of every interest payment
+}
+
+// Merchant partner accepting "buy now, pay later" purchases
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct Merchant {
+    pub name: String,
+    pub discount_bps: i128, // of each purchase, kept to fund its zero-interest plan
+}
+
+// Merchant and refunds of a loan opened by bnpl_purchase
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct BnplPurchase {
+    pub merchant: Address,
+    pub amount: i128,
+    pub refunded: i128,
+}
+
+// Solidarity group of verified borrowers backing each other's circle loans
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct LendingCircle {
+    pub members: Vec<Address>,
+    pub liability_bps: i128, // share of a defaulted member's unpaid principal the others owe
+    pub score: i128,         // collective score, scales the members' circle loan limits
+    pub repaid_count: u32,
+    pub defaulted_count: u32,
+    pub active_loans: u32,     // circle loans not yet repaid or defaulted
+    pub open_obligations: u32, // joint obligations not yet settled
+}
+
+// Joint liability of the other circle members for a defaulted circle loan
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct JointObligation {
+    pub circle_id: u32,
+    pub owed: Map<Address, i128>, // what each member still owes
+    pub recovered: i128,          // paid so far to the loan's lenders
+    pub deadline: u64,
+    pub settled: bool,
+}
+
+// Co-signer of a loan
+// The loan principal is reserved from the guarantor's own credit limit while the loan is
+// active, and overdue installments are charged to them through a token allowance.
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct Guarantee {
+    pub guarantor: Address,
+    pub reserved: i128, // principal reserved from the guarantor's limit
+    pub charged: i128,  // installments paid by the guarantor so far
+}
+
+// Interest rates charged to borrowers scoring at least `min_score`
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct RateTier {
+    pub min_score: i128,
+    pub rate: i128,      // per loan, in basis points
+    pub line_rate: i128, // annual rate of credit lines, in basis points
+}
+
+// SEP-40 price feed used to value collateral
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct OracleConfig {
+    pub oracle: Address,
+    pub max_age: u64, // seconds after which a price is considered stale
+}
+
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum CreditLineStatus {
+    Open = 0,
+    Closed = 1,
+    Defaulted = 2,
+    WrittenOff = 3,
+}
+
+// Revolving credit line, one per borrower
+// Interest accrues per second on the drawn principal. Every PAYMENT_PERIOD a statement
+// sets the minimum payment due by the end of the next period.
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct CreditLine {
+    pub borrower: Address,
+    pub limit: i128,             // approved from max_loan when the line was opened
+    pub rate: i128,              // annual, in basis points
+    pub drawn: i128,             // principal drawn and not repaid
+    pub interest: i128,          // accrued interest not yet paid
+    pub accrued_at: u64,         // last time interest was accrued
+    pub next_due: u64,           // end of the current payment period
+    pub min_due: i128,           // minimum payment still owed by next_due
+    pub pool: Option<Address>,   // lending pool funding the draws, if any
+    pub status: CreditLineStatus,
+}
+
+// Discriminants of the first three variants match the integers stored by the
+// legacy layout, so old records map one to one. The value is now encoded as u32
+// instead of i32: clients reading the raw field must decode it as u32.
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
 pub enum LoanStatus {
     Active = 0,
     Repaid = 1,
     Defaulted = 2,
+    Pending = 3,   // created for a posted request, not yet funded
+    Cancelled = 4, // request withdrawn, refunded or expired before funding
+    WrittenOff = 5,
+}
+
+impl LoanStatus {
+    /// Map the raw integer stored by the legacy loan layout
+    fn from_legacy(status: i32) -> Self {
+        match status {
+            0 => LoanStatus::Active,
+            1 => LoanStatus::Repaid,
+            2 => LoanStatus::Defaulted,
+            _ => panic!("Unknown legacy loan status"),
+        }
+    }
+}
+
+// Loan layout written before status became a contracttype enum.
+// Only used to read and migrate old entries, never written.
+#[derive(Clone)]
+#[contracttype(export = false)]
+pub struct LegacyLoan {
+    pub borrower: Address,
+    pub amount: i128,
+    pub total_amount: i128,
+    pub interest_rate: i128,
+    pub num_payments: u32,
+    pub paid_payments: u32,
+    pub payment_amount: i128,
+    pub created_at: i128,
+    pub status: i32,
+}
+
+impl From<LegacyLoan> for Loan {
+    fn from(legacy: LegacyLoan) -> Self {
+        Loan {
+            borrower: legacy.borrower,
+            amount: legacy.amount,
+            total_amount: legacy.total_amount,
+            interest_rate: legacy.interest_rate,
+            num_payments: legacy.num_payments,
+            paid_payments: legacy.paid_payments,
+            payment_amount: legacy.payment_amount,
+            created_at: legacy.created_at,
+            status: LoanStatus::from_legacy(legacy.status),
+        }
+    }
 }
 
 const CREDIT_SCORING: Symbol = symbol_short!("CREDIT_SC");
 const TOKEN: Symbol = symbol_short!("TOKEN");
 const INTEREST_RATE: Symbol = symbol_short!("INT_RATE"); // Default interest rate in basis points
+const MAX_PAGE_SIZE: u32 = 50; // Upper bound on loan ids scanned by list_loans
+const STATS: Symbol = symbol_short!("STATS");
+const COLLATERAL_CFG: Symbol = symbol_short!("COLL_CFG");
+const ORACLE_CFG: Symbol = symbol_short!("ORCL_CFG");
+const LIQUIDATION_CFG: Symbol = symbol_short!("LIQ_CFG");
+const IDENTITY: Symbol = symbol_short!("IDENTITY");
+const OFFER_ID: Symbol = symbol_short!("OFFER_ID");
+const REQUEST_ID: Symbol = symbol_short!("REQ_ID");
+const LENDING_POOL: Symbol = symbol_short!("POOL");
+const RESERVE: Symbol = symbol_short!("RESERVE"); // Loss reserve held by this contract
+const RESERVE_RATE: Symbol = symbol_short!("RSV_RATE"); // Share of interest kept for the reserve
+const FEE_CFG: Symbol = symbol_short!("FEE_CFG");
+const FEES: Symbol = symbol_short!("FEES"); // Fees ever collected
+const TREASURY: Symbol = symbol_short!("TREASURY");
+const RATE_TIERS: Symbol = symbol_short!("RATE_TRS");
+const PAYMENT_PERIOD: i128 = 30 * 24 * 60 * 60; // Seconds between installments
+const GRACE_PERIOD: i128 = 7 * 24 * 60 * 60; // Seconds an installment may be late before default
+const DEFAULT_PENALTY: i128 = 50; // Score points removed when a loan defaults
+const MODIFICATION_PENALTY: i128 = 15; // Score points removed per restructuring or forbearance
+const MISSED_PAYMENT_PENALTY: i128 = 15; // Score points removed when a borrower's guarantor pays
+const GUARANTOR_REWARD: i128 = 5; // Score points for guaranteeing a loan repaid on time
+const GUARANTOR_CHARGE_PENALTY: i128 = 10; // Score points removed when a guarantor is charged
+const GUARANTOR_PENALTY: i128 = 25; // Score points removed when a guaranteed loan defaults
+const CIRCLE_ID: Symbol = symbol_short!("CIRC_ID");
+const CIRCLE_INITIAL_SCORE: i128 = 100; // Collective score of a new circle, a 1x limit
+const CIRCLE_MAX_SCORE: i128 = 200; // Collective score at which circle limits stop growing
+const CIRCLE_REWARD: i128 = 10; // Collective points per circle loan repaid
+const CIRCLE_PENALTY: i128 = 50; // Collective points removed when joint liability is unmet
+const YEAR: i128 = 365 * 24 * 60 * 60; // Seconds per year, for credit line interest
+const MIN_PAYMENT_BPS: i128 = 1000; // Share of a credit line balance due every period
+const LINE_RATE: Symbol = symbol_short!("LINE_RATE"); // Annual rate of new credit lines
 
 #[contract]
 pub struct LoanManagerMicro;
@@ -36,10 +338,12 @@ impl LoanManagerMicro {
     /// Initialize the contract with dependencies
     pub fn initialize(
         env: Env,
+        owner: Address,
         credit_scoring: Address,
         token_address: Address,
         default_interest_rate: i128,
     ) {
+        access_control::init_owner(&env, &owner);
         env.storage().instance().set(&CREDIT_SCORING, &credit_scoring);
         env.storage().instance().set(&TOKEN, &token_address);
         env.storage()
@@ -47,6 +351,59 @@ impl LoanManagerMicro {
             .set(&INTEREST_RATE, &default_interest_rate);
     }
 
+    /// Get the owner of the contract
+    pub fn get_owner(env: Env) -> Address {
+        access_control::get_owner(&env)
+    }
+
+    /// Start a two-step ownership transfer (only owner)
+    /// The new owner must call accept_ownership to complete it
+    pub fn transfer_ownership(env: Env, new_owner: Address) {
+        access_control::transfer_ownership(&env, &new_owner);
+    }
+
+    /// Complete a pending ownership transfer (only the pending owner)
+    pub fn accept_ownership(env: Env) {
+        access_control::accept_ownership(&env);
+    }
+
+    /// Get the pending owner of an unfinished ownership transfer
+    pub fn get_pending_owner(env: Env) -> Option<Address> {
+        access_control::get_pending_owner(&env)
+    }
+
+    /// Grant a role (owner, or an admin for non-admin roles)
+    pub fn grant_role(env: Env, caller: Address, role: Role, account: Address) {
+        access_control::grant_role(&env, &caller, role, &account);
+    }
+
+    /// Revoke a role (owner, or an admin for non-admin roles)
+    pub fn revoke_role(env: Env, caller: Address, role: Role, account: Address) {
+        access_control::revoke_role(&env, &caller, role, &account);
+    }
+
+    /// Check if an account was granted a role
+    pub fn has_role(env: Env, role: Role, account: Address) -> bool {
+        access_control::has_role(&env, role, &account)
+    }
+
+    /// Pause an operation (owner or pauser)
+    /// Pausing NewLoans leaves repayments open so borrowers can still pay down debt
+    pub fn pause(env: Env, caller: Address, flag: PauseFlag) {
+        access_control::require_role(&env, Role::Pauser, &caller);
+        pause::set_paused(&env, flag, true);
+    }
+
+    /// Unpause an operation (owner or pauser)
+    pub fn unpause(env: Env, caller: Address, flag: PauseFlag) {
+        access_control::require_role(&env, Role::Pauser, &caller);
+        pause::set_paused(&env, flag, false);
+    }
+
+    /// Check if an operation is paused
+    pub fn is_paused(env: Env, flag: PauseFlag) -> bool {
+        pause::is_paused(&env, flag)
+    }
     /// Get credit scoring contract address
     fn get_credit_scoring(env: &Env) -> Address {
         env.storage()
@@ -63,15 +420,356 @@ impl LoanManagerMicro {
             .expect("Token not initialized")
     }
 
-    /// Request a loan
+    /// Request an unsecured loan, limited by the borrower's credit score
+    /// An optional guarantor co-signs: the limit becomes their available headroom instead,
+    /// all of the principal is reserved from it, and they must approve this contract to
+    /// spend their tokens so missed installments can be charged to them.
     pub fn request_loan(
         env: Env,
         borrower: Address,
         amount: i128,
         num_payments: u32,
+        guarantor: Option<Address>,
+    ) -> u32 {
+        borrower.require_auth();
+
+        let Some(guarantor) = guarantor else {
+            return Self::open_loan(env, borrower, amount, num_payments, 0);
+        };
+
+        guarantor.require_auth();
+        if guarantor == borrower {
+            panic!("Cannot guarantee your own loan");
+        }
+        if Self::is_blacklisted(&env, &guarantor) {
+            panic!("Guarantor is blacklisted");
+        }
+        let headroom = Self::available_limit(&env, &guarantor);
+        if amount > headroom {
+            panic!("Amount exceeds guarantor headroom");
+        }
+
+        let extra_limit = (headroom - Self::available_limit(&env, &borrower)).max(0);
+        let loan_id =
+            Self::open_loan(env.clone(), borrower, amount, num_payments, extra_limit);
+
+        Self::adjust_guaranteed(&env, &guarantor, amount);
+        env.storage().persistent().set(
+            &(symbol_short!("GUARANTEE"), loan_id),
+            &Guarantee {
+                guarantor,
+                reserved: amount,
+                charged: 0,
+            },
+        );
+        loan_id
+    }
+
+    /// Get the guarantee of a loan, if it has a guarantor
+    pub fn get_guarantee(env: Env, loan_id: u32) -> Option<Guarantee> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("GUARANTEE"), loan_id))
+    }
+
+    /// Get the principal reserved from a guarantor's limit by the active loans they guarantee
+    pub fn get_guaranteed(env: Env, guarantor: Address) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("GUAR_RES"), guarantor))
+            .unwrap_or(0)
+    }
+
+    /// A borrower's `max_loan` less what is reserved for the loans they guarantee
+    fn available_limit(env: &Env, borrower: &Address) -> i128 {
+        let max_loan = Self::get_max_loan_amount(env.clone(), borrower.clone());
+        (max_loan - Self::get_guaranteed(env.clone(), borrower.clone())).max(0)
+    }
+
+    fn adjust_guaranteed(env: &Env, guarantor: &Address, delta: i128) {
+        let reserved = Self::get_guaranteed(env.clone(), guarantor.clone()) + delta;
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("GUAR_RES"), guarantor.clone()), &reserved);
+    }
+
+    /// Release the guarantor's reservation once a loan is closed or defaulted
+    fn release_guarantee(env: &Env, loan_id: u32) -> Option<Guarantee> {
+        let guarantee = Self::get_guarantee(env.clone(), loan_id)?;
+        if guarantee.reserved > 0 {
+            Self::adjust_guaranteed(env, &guarantee.guarantor, -guarantee.reserved);
+            let released = Guarantee {
+                reserved: 0,
+                ..guarantee.clone()
+            };
+            env.storage()
+                .persistent()
+                .set(&(symbol_short!("GUARANTEE"), loan_id), &released);
+        }
+        Some(guarantee)
+    }
+
+    /// Charge the guarantor of a loan for every installment overdue past the grace period
+    /// Anyone can call this. The installments are taken through the guarantor's allowance
+    /// to this contract and paid to the lenders; the borrower's score is penalized. Returns
+    /// the amount charged.
+    pub fn charge_guarantor(env: Env, loan_id: u32) -> i128 {
+        let loan = Self::get_loan(env.clone(), loan_id);
+        if loan.status != LoanStatus::Active {
+            panic!("Loan is not active");
+        }
+        if Self::get_guarantee(env.clone(), loan_id).is_none() {
+            panic!("Loan has no guarantor");
+        }
+        if Self::overdue_installments(&env, loan_id, &loan) == 0 {
+            panic!("Loan is not past due");
+        }
+
+        Self::try_charge_guarantor(&env, loan_id, loan).expect("Guarantor could not be charged")
+    }
+
+    /// Installments of a loan that are overdue past the grace period
+    fn overdue_installments(env: &Env, loan_id: u32, loan: &Loan) -> u32 {
+        let deferred: i128 = env
+            .storage()
+            .persistent()
+            .get(&(symbol_short!("DEFERRED"), loan_id))
+            .unwrap_or(0);
+        let late = env.ledger().timestamp() as i128 - loan.created_at - deferred - GRACE_PERIOD;
+        if late <= 0 {
+            return 0;
+        }
+        // Installment k is overdue once k * PAYMENT_PERIOD < late
+        let overdue = ((late - 1) / PAYMENT_PERIOD) as u32;
+        overdue
+            .saturating_sub(loan.paid_payments)
+            .min(loan.num_payments - loan.paid_payments)
+    }
+
+    /// Take the overdue installments of a guaranteed loan from its guarantor
+    /// Returns None, changing nothing, when the allowance or balance falls short.
+    fn try_charge_guarantor(env: &Env, loan_id: u32, mut loan: Loan) -> Option<i128> {
+        let mut guarantee = Self::get_guarantee(env.clone(), loan_id)?;
+        let count = Self::overdue_installments(env, loan_id, &loan);
+        let amount = Self::installments_amount(env, loan_id, &loan, count);
+
+        let contract = env.current_contract_address();
+        let token_client = soroban_sdk::token::Client::new(env, &Self::get_token_address(env));
+        let pulled =
+            token_client.try_transfer_from(&contract, &guarantee.guarantor, &contract, &amount);
+        if !matches!(pulled, Ok(Ok(()))) {
+            return None;
+        }
+
+        let principal_part = Self::record_installments(env, loan_id, &mut loan, count);
+        Self::pay_lenders_from_contract(env, loan_id, amount, principal_part);
+
+        guarantee.charged += amount;
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("GUARANTEE"), loan_id), &guarantee);
+
+        if loan.paid_payments >= loan.num_payments {
+            Self::close_repaid_loan(env, loan_id, &mut loan);
+        }
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("LOAN"), loan_id), &loan);
+
+        Self::penalize_borrower(env.clone(), loan.borrower, MISSED_PAYMENT_PENALTY);
+        Self::penalize_borrower(env.clone(), guarantee.guarantor, GUARANTOR_CHARGE_PENALTY);
+        Some(amount)
+    }
+
+    /// Request a loan secured by collateral locked in escrow
+    /// The score-based limit is raised by the collateral value times the loan-to-value ratio.
+    /// The collateral is returned on full repayment and forfeited on default.
+    pub fn request_secured_loan(
+        env: Env,
+        borrower: Address,
+        amount: i128,
+        num_payments: u32,
+        collateral_amount: i128,
     ) -> u32 {
         borrower.require_auth();
 
+        if collateral_amount <= 0 {
+            panic!("Collateral must be positive");
+        }
+
+        let config = Self::get_collateral_config(env.clone())
+            .expect("Secured loans are not configured");
+
+        let extra_limit = Self::collateral_value(&env, &config.token, collateral_amount)
+            .checked_mul(config.ltv_bps)
+            .expect("Collateral value overflow")
+            / 10000;
+        let loan_id = Self::open_loan(
+            env.clone(),
+            borrower.clone(),
+            amount,
+            num_payments,
+            extra_limit,
+        );
+
+        // Lock the collateral in this contract until the loan is closed
+        let collateral_token = soroban_sdk::token::Client::new(&env, &config.token);
+        collateral_token.transfer(
+            &borrower,
+            env.current_contract_address(),
+            &collateral_amount,
+        );
+
+        let position = CollateralPosition {
+            token: config.token.clone(),
+            amount: collateral_amount,
+        };
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("COLLAT"), loan_id), &position);
+        Self::adjust_escrow(&env, &config.token, collateral_amount);
+
+        loan_id
+    }
+
+    /// Create and disburse a protocol-funded loan once the borrower has authorized it
+    /// `extra_limit` is added to the score-based limit (collateral backing, for secured loans)
+    fn open_loan(
+        env: Env,
+        borrower: Address,
+        amount: i128,
+        num_payments: u32,
+        extra_limit: i128,
+    ) -> u32 {
+        Self::check_borrower(&env, &borrower, amount, num_payments, extra_limit);
+
+        let interest_rate = Self::get_rate_for(env.clone(), borrower.clone());
+
+        // With an origination fee the principal passes through this contract, which
+        // keeps the fee.
+        let charges_fee = Self::get_fee_config(env.clone()).origination_fee_bps > 0;
+        let recipient = if charges_fee {
+            env.current_contract_address()
+        } else {
+            borrower.clone()
+        };
+
+        let loan_id =
+            Self::fund_loan(&env, &borrower, &recipient, amount, interest_rate, num_payments);
+        if charges_fee {
+            Self::send_principal(&env, &borrower, amount);
+        }
+        loan_id
+    }
+
+    /// Disburse the principal of a new protocol loan to `recipient` and store the loan
+    /// Disburse BEFORE storing the loan: from the lending pool when one is set, otherwise
+    /// by minting.
+    fn fund_loan(
+        env: &Env,
+        borrower: &Address,
+        recipient: &Address,
+        amount: i128,
+        interest_rate: i128,
+        num_payments: u32,
+    ) -> u32 {
+        let lending_pool = Self::get_lending_pool(env.clone());
+        match &lending_pool {
+            Some(pool) => LendingPoolMiniClient::new(env, pool).disburse_loan(recipient, &amount),
+            None => Self::mint_loan_tokens(env, recipient.clone(), amount),
+        }
+
+        let loan_id = Self::store_loan(env, borrower, amount, interest_rate, num_payments);
+        if let Some(pool) = lending_pool {
+            env.storage()
+                .persistent()
+                .set(&(symbol_short!("POOLED"), loan_id), &pool);
+        }
+        loan_id
+    }
+
+    /// Set the interest rate tiers by credit score (owner or risk manager)
+    /// A borrower pays the rates of the highest tier whose minimum score they reach, or the
+    /// default loan and line rates below every tier.
+    pub fn set_rate_tiers(env: Env, caller: Address, tiers: Vec<RateTier>) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+
+        for tier in tiers.iter() {
+            if !(0..=10000).contains(&tier.rate) || !(0..=10000).contains(&tier.line_rate) {
+                panic!("Interest rate must be between 0 and 10000 basis points");
+            }
+        }
+
+        env.storage().instance().set(&RATE_TIERS, &tiers);
+    }
+
+    /// Get the interest rate tiers by credit score (empty by default)
+    pub fn get_rate_tiers(env: Env) -> Vec<RateTier> {
+        env.storage()
+            .instance()
+            .get(&RATE_TIERS)
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Get the interest rate a new loan to `borrower` would be priced at
+    pub fn get_rate_for(env: Env, borrower: Address) -> i128 {
+        match Self::rate_tier(&env, &borrower) {
+            Some(tier) => tier.rate,
+            None => env
+                .storage()
+                .instance()
+                .get(&INTEREST_RATE)
+                .unwrap_or(500), // Default 5%
+        }
+    }
+
+    /// Get the annual rate a new credit line of `borrower` would be priced at
+    pub fn get_line_rate_for(env: Env, borrower: Address) -> i128 {
+        match Self::rate_tier(&env, &borrower) {
+            Some(tier) => tier.line_rate,
+            None => Self::get_line_rate(env),
+        }
+    }
+
+    /// Highest rate tier whose minimum score the borrower reaches, if any
+    fn rate_tier(env: &Env, borrower: &Address) -> Option<RateTier> {
+        let tiers = Self::get_rate_tiers(env.clone());
+        if tiers.is_empty() {
+            return None;
+        }
+
+        let score = Self::get_score(env, borrower);
+        let mut best: Option<RateTier> = None;
+        for tier in tiers.iter() {
+            let higher = best
+                .as_ref()
+                .is_none_or(|best| tier.min_score > best.min_score);
+            if score >= tier.min_score && higher {
+                best = Some(tier);
+            }
+        }
+        best
+    }
+
+    /// Helper to get a user's score value from credit scoring
+    fn get_score(env: &Env, user: &Address) -> i128 {
+        env.invoke_contract(
+            &Self::get_credit_scoring(env),
+            &Symbol::new(env, "get_score_value"),
+            soroban_sdk::vec![env, user.clone().into_val(env)],
+        )
+    }
+
+    /// Check a new loan request against the pause flag, blacklist and credit limit
+    fn check_borrower(
+        env: &Env,
+        borrower: &Address,
+        amount: i128,
+        num_payments: u32,
+        extra_limit: i128,
+    ) {
+        pause::require_not_paused(env, PauseFlag::NewLoans);
+
         if amount <= 0 {
             panic!("Amount must be positive");
         }
@@ -85,16 +783,7 @@ impl LoanManagerMicro {
             panic!("Contract not initialized. Credit scoring address not set.");
         }
 
-        // Check if user is blacklisted
-        let credit_scoring_addr = Self::get_credit_scoring(&env);
-        let is_blacklisted: bool = env
-            .invoke_contract(
-                &credit_scoring_addr,
-                &symbol_short!("is_blk"), // Shortened to fit 9 char limit
-                soroban_sdk::vec![&env, borrower.clone().into_val(&env)],
-            );
-        
-        if is_blacklisted {
+        if Self::is_blacklisted(env, borrower) {
             panic!("User is blacklisted");
         }
         
@@ -103,17 +792,40 @@ impl LoanManagerMicro {
         if max_loan == 0 {
             panic!("User has no credit score. Please initialize your credit score first.");
         }
-        if amount > max_loan {
+        if amount > Self::available_limit(env, borrower) + extra_limit {
             panic!("Amount exceeds maximum loan limit");
         }
+    }
 
-        // Calculate interest
-        let interest_rate: i128 = env
-            .storage()
-            .instance()
-            .get(&INTEREST_RATE)
-            .unwrap_or(500); // Default 5%
+    /// Store a new active loan whose principal has been disbursed
+    fn store_loan(
+        env: &Env,
+        borrower: &Address,
+        amount: i128,
+        interest_rate: i128,
+        num_payments: u32,
+    ) -> u32 {
+        let loan_id = Self::create_loan(
+            env,
+            borrower,
+            amount,
+            interest_rate,
+            num_payments,
+            LoanStatus::Active,
+        );
+        Self::record_disbursal(env, loan_id, borrower, amount);
+        loan_id
+    }
 
+    /// Store a new loan record with the given status
+    fn create_loan(
+        env: &Env,
+        borrower: &Address,
+        amount: i128,
+        interest_rate: i128,
+        num_payments: u32,
+        status: LoanStatus,
+    ) -> u32 {
         let interest = (amount * interest_rate) / 10000;
         let total_amount = amount + interest;
         
@@ -125,14 +837,9 @@ impl LoanManagerMicro {
             total_amount / (num_payments as i128)
         };
 
-        // Generate loan ID first
         let loan_id = Self::get_next_loan_id(env.clone());
         Self::increment_loan_id(env.clone());
 
-        // Mint tokens directly to borrower BEFORE storing the loan
-        // If minting fails, we don't want to store an invalid loan
-        Self::mint_loan_tokens(&env, borrower.clone(), amount);
-
         // Create loan
         let loan = Loan {
             borrower: borrower.clone(),
@@ -143,22 +850,41 @@ impl LoanManagerMicro {
             paid_payments: 0,
             payment_amount,
             created_at: env.ledger().timestamp() as i128,
-            status: LoanStatus::Active as i32,
+            status,
         };
 
-        // Store loan after successful minting
         let loan_key = (symbol_short!("LOAN"), loan_id);
         env.storage()
             .persistent()
             .set(&loan_key, &loan);
-        let borrower_key = (symbol_short!("BORROWER"), borrower.clone());
-        env.storage()
-            .persistent()
-            .set(&borrower_key, &loan_id);
 
         loan_id
     }
 
+    /// Make a disbursed loan the borrower's current one and count it in the stats
+    fn record_disbursal(env: &Env, loan_id: u32, borrower: &Address, amount: i128) {
+        let borrower_key = (symbol_short!("BORROWER"), borrower.clone());
+        env.storage()
+            .persistent()
+            .set(&borrower_key, &loan_id);
+
+        let mut stats = Self::get_stats(env.clone());
+        stats.total_disbursed += amount;
+        stats.outstanding_principal += amount;
+        stats.active_count += 1;
+        env.storage().instance().set(&STATS, &stats);
+    }
+
+    /// Helper to check the blacklist in credit scoring
+    fn is_blacklisted(env: &Env, user: &Address) -> bool {
+        let credit_scoring_addr = Self::get_credit_scoring(env);
+        env.invoke_contract(
+            &credit_scoring_addr,
+            &symbol_short!("is_blk"), // Shortened to fit 9 char limit
+            soroban_sdk::vec![env, user.clone().into_val(env)],
+        )
+    }
+
     /// Helper to get max loan amount from credit scoring
     fn get_max_loan_amount(env: Env, user: Address) -> i128 {
         let credit_scoring_addr = Self::get_credit_scoring(&env);
@@ -188,22 +914,19 @@ impl LoanManagerMicro {
         );
     }
 
-    /// Pay a loan installment
+    /// Pay a loan installment (the last one carries the rounding remainder)
     pub fn pay_installment(env: Env, borrower: Address, loan_id: u32) {
         borrower.require_auth();
+        pause::require_not_paused(&env, PauseFlag::Repayments);
 
         let loan_key = (symbol_short!("LOAN"), loan_id);
-        let mut loan: Loan = env
-            .storage()
-            .persistent()
-            .get(&loan_key)
-            .expect("Loan not found");
+        let mut loan = Self::get_loan(env.clone(), loan_id);
 
         if loan.borrower != borrower {
             panic!("Not your loan");
         }
 
-        if loan.status != LoanStatus::Active as i32 {
+        if loan.status != LoanStatus::Active {
             panic!("Loan is not active");
         }
 
@@ -211,26 +934,23 @@ impl LoanManagerMicro {
             panic!("Loan already fully paid");
         }
 
-        // Transfer payment from borrower
-        let token_address = Self::get_token_address(&env);
-        let token_client = soroban_sdk::token::Client::new(&env, &token_address);
-        token_client.transfer(
-            &borrower,
-            &env.current_contract_address(),
-            &loan.payment_amount,
-        );
-
-        // Tokens are already in the contract from the transfer above
-        // No need to register repayment in pool (pool removed)
+        let amount = Self::installments_amount(&env, loan_id, &loan, 1);
+        let principal_part = Self::record_installments(&env, loan_id, &mut loan, 1);
 
-        // Update loan
-        loan.paid_payments += 1;
+        // Transfer payment from borrower to the lenders (this contract for protocol loans)
+        Self::pay_lenders(&env, loan_id, &borrower, amount, principal_part);
 
         // Check if fully paid
         if loan.paid_payments >= loan.num_payments {
-            loan.status = LoanStatus::Repaid as i32;
-            // Reward user in credit scoring
-            Self::reward_user(env.clone(), borrower.clone());
+            Self::close_repaid_loan(&env, loan_id, &mut loan);
+            Self::circle_loan_repaid(&env, loan_id);
+            // Reward user in credit scoring (10 points for a successful repayment)
+            Self::reward_user(env.clone(), borrower.clone(), 10);
+            if let Some(guarantee) = Self::get_guarantee(env.clone(), loan_id) {
+                if guarantee.charged == 0 {
+                    Self::reward_user(env.clone(), guarantee.guarantor, GUARANTOR_REWARD);
+                }
+            }
         }
 
         env.storage()
@@ -238,67 +958,4354 @@ impl LoanManagerMicro {
             .set(&loan_key, &loan);
     }
 
+    /// Mark `count` installments of an active loan as paid and update the portfolio stats
+    /// The principal of each installment is amortized in proportion to the debt it pays,
+    /// using cumulative amounts so rounding never leaves principal behind. Returns the
+    /// principal part of the payment.
+    fn record_installments(env: &Env, loan_id: u32, loan: &mut Loan, count: u32) -> i128 {
+        let amount = Self::installments_amount(env, loan_id, loan, count);
+        let unpaid_before = Self::unpaid_principal(env, loan_id, loan);
+        loan.paid_payments += count;
+        let unpaid_after = Self::unpaid_principal(env, loan_id, loan);
+
+        let principal_part = (unpaid_before - unpaid_after).min(amount);
+
+        let mut stats = Self::get_stats(env.clone());
+        stats.total_repaid += amount;
+        stats.outstanding_principal -= principal_part;
+        stats.interest_earned += amount - principal_part;
+        env.storage().instance().set(&STATS, &stats);
+
+        principal_part
+    }
+
+    /// Close a loan whose installments are all paid and return its collateral
+    fn close_repaid_loan(env: &Env, loan_id: u32, loan: &mut Loan) {
+        loan.status = LoanStatus::Repaid;
+
+        let mut stats = Self::get_stats(env.clone());
+        stats.active_count -= 1;
+        stats.repaid_count += 1;
+        env.storage().instance().set(&STATS, &stats);
+
+        Self::release_collateral(env, loan_id, &loan.borrower);
+        Self::release_guarantee(env, loan_id);
+    }
+
     /// Helper to reward user in credit scoring
-    fn reward_user(env: Env, user: Address) {
+    fn reward_user(env: Env, user: Address, points: i128) {
         let credit_scoring_addr = Self::get_credit_scoring(&env);
-        // Reward with 10 points for successful loan repayment
         env.invoke_contract::<()>(
             &credit_scoring_addr,
             &symbol_short!("reward"), // Shortened to fit 9 char limit
-            soroban_sdk::vec![&env, user.into_val(&env), 10i128.into_val(&env)],
+            soroban_sdk::vec![
+                &env,
+                env.current_contract_address().into_val(&env),
+                user.into_val(&env),
+                points.into_val(&env),
+            ],
         );
     }
 
-    /// Get loan information
-    pub fn get_loan(env: Env, loan_id: u32) -> Loan {
+    /// Helper to penalize user in credit scoring
+    fn penalize_borrower(env: Env, user: Address, points: i128) {
+        let credit_scoring_addr = Self::get_credit_scoring(&env);
+        env.invoke_contract::<()>(
+            &credit_scoring_addr,
+            &Symbol::new(&env, "penalize_user"),
+            soroban_sdk::vec![
+                &env,
+                env.current_contract_address().into_val(&env),
+                user.into_val(&env),
+                points.into_val(&env),
+            ],
+        );
+    }
+
+    /// Installment the current schedule of a loan starts at
+    /// Non-zero once a partial BNPL refund has spread the rest of the debt over the
+    /// installments left, keeping the ones already paid.
+    fn schedule_start(env: &Env, loan_id: u32) -> u32 {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("SCHED"), loan_id))
+            .unwrap_or(0)
+    }
+
+    /// Principal not yet covered by the installments paid
+    /// Principal is amortized in proportion to the debt paid, so the remainder carried by
+    /// the last installment is split like any other.
+    fn unpaid_principal(env: &Env, loan_id: u32, loan: &Loan) -> i128 {
+        loan.amount * Self::remaining_debt(env, loan_id, loan) / loan.total_amount.max(1)
+    }
+
+    /// Get the timestamp the next installment of a loan is due
+    /// Installments fall due every PAYMENT_PERIOD after the loan was created
+    /// (shifted by any forbearance granted).
+    pub fn get_next_due_date(env: Env, loan_id: u32) -> i128 {
+        let loan = Self::get_loan(env.clone(), loan_id);
+        let deferred: i128 = env
+            .storage()
+            .persistent()
+            .get(&(symbol_short!("DEFERRED"), loan_id))
+            .unwrap_or(0);
+        loan.created_at + (loan.paid_payments as i128 + 1) * PAYMENT_PERIOD + deferred
+    }
+
+    /// Mark an active loan as defaulted once an installment is overdue past the grace period
+    /// Anyone can call this. A guaranteed loan is first charged to its guarantor and only
+    /// defaults if that fails. Collateral of a secured loan is forfeited, the other members
+    /// of a circle take on their joint liability, and the borrower's (and guarantor's)
+    /// score is penalized.
+    pub fn mark_defaulted(env: Env, loan_id: u32) {
         let loan_key = (symbol_short!("LOAN"), loan_id);
+        let mut loan = Self::get_loan(env.clone(), loan_id);
+
+        if loan.status != LoanStatus::Active {
+            panic!("Loan is not active");
+        }
+
+        let due_date = Self::get_next_due_date(env.clone(), loan_id);
+        if (env.ledger().timestamp() as i128) <= due_date + GRACE_PERIOD {
+            panic!("Loan is not past due");
+        }
+
+        // A guarantor able to pay keeps the loan out of default
+        if Self::try_charge_guarantor(&env, loan_id, loan.clone()).is_some() {
+            return;
+        }
+
+        loan.status = LoanStatus::Defaulted;
         env.storage()
             .persistent()
-            .get(&loan_key)
-            .expect("Loan not found")
+            .set(&loan_key, &loan);
+
+        let outstanding = Self::unpaid_principal(&env, loan_id, &loan);
+        let mut stats = Self::get_stats(env.clone());
+        stats.outstanding_principal -= outstanding;
+        stats.active_count -= 1;
+        stats.defaulted_count += 1;
+        env.storage().instance().set(&STATS, &stats);
+
+        Self::forfeit_collateral(&env, loan_id);
+        Self::open_joint_obligation(&env, loan_id, &loan.borrower, outstanding);
+        if let Some(guarantee) = Self::release_guarantee(&env, loan_id) {
+            Self::penalize_borrower(env.clone(), guarantee.guarantor, GUARANTOR_PENALTY);
+        }
+        Self::penalize_borrower(env.clone(), loan.borrower, DEFAULT_PENALTY);
     }
 
-    /// Get loan ID for a borrower
-    pub fn get_loan_id(env: Env, borrower: Address) -> Option<u32> {
-        let borrower_key = (symbol_short!("BORROWER"), borrower);
+    /// Write off a defaulted loan (owner or admin)
+    /// The unpaid principal is first netted against the current value of the collateral the
+    /// lenders received on default, then covered from the loss reserve as far as it goes
+    /// and paid to the lenders; a pool absorbs whatever is left, junior tranche first.
+    /// Loans minted by the protocol have no lenders and take nothing from the reserve.
+    /// Returns the amount covered by the reserve.
+    pub fn write_off(env: Env, caller: Address, loan_id: u32) -> i128 {
+        access_control::require_role(&env, Role::Admin, &caller);
+
+        let loan_key = (symbol_short!("LOAN"), loan_id);
+        let mut loan = Self::get_loan(env.clone(), loan_id);
+
+        if loan.status != LoanStatus::Defaulted {
+            panic!("Loan is not defaulted");
+        }
+
+        // Principal recovered from the other members of a circle is no longer owed
+        let mut shortfall = Self::unpaid_principal(&env, loan_id, &loan);
+        if let Some(obligation) = Self::get_joint_obligation(env.clone(), loan_id) {
+            if !obligation.settled {
+                panic!("Joint obligation not settled");
+            }
+            shortfall -= obligation.recovered;
+        }
+
+        // So is the value of the forfeited collateral, which a pool keeps on its books
+        let pool = Self::get_loan_pool(&env, loan_id);
+        if let Some(position) = Self::get_forfeited(env.clone(), loan_id) {
+            let recovered = Self::collateral_value(&env, &position.token, position.amount)
+                .min(shortfall);
+            shortfall -= recovered;
+            if let Some(pool) = &pool {
+                LendingPoolMiniClient::new(&env, pool).register_recovery(
+                    &position.token,
+                    &position.amount,
+                    &recovered,
+                );
+            }
+        }
+
+        // Nobody is owed the principal of a minted loan, so the reserve is kept
+        let covered = if Self::is_protocol_funded(&env, loan_id) {
+            0
+        } else {
+            shortfall.min(Self::reserve_balance(env.clone()))
+        };
+        Self::set_reserve(&env, Self::reserve_balance(env.clone()) - covered);
+
+        if covered > 0 {
+            Self::pay_lenders_from_contract(&env, loan_id, covered, covered);
+        }
+
+        if let Some(pool) = pool {
+            LendingPoolMiniClient::new(&env, &pool).register_loss(&(shortfall - covered));
+        }
+
+        loan.status = LoanStatus::WrittenOff;
         env.storage()
             .persistent()
-            .get(&borrower_key)
-            .unwrap_or(None)
+            .set(&loan_key, &loan);
+
+        Self::require_backed(&env);
+        covered
     }
 
-    /// Get next loan ID
-    fn get_next_loan_id(env: Env) -> u32 {
+    /// Set the share of every interest payment kept for the loss reserve (owner or risk manager)
+    pub fn set_reserve_rate(env: Env, caller: Address, rate_bps: i128) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+
+        let protocol_fee_bps = Self::get_fee_config(env.clone()).protocol_fee_bps;
+        if rate_bps < 0 || rate_bps + protocol_fee_bps > 10000 {
+            panic!("Reserve rate plus protocol fee must be between 0 and 10000 basis points");
+        }
+
+        env.storage().instance().set(&RESERVE_RATE, &rate_bps);
+    }
+
+    /// Get the share of interest kept for the loss reserve, in basis points (default 0)
+    pub fn get_reserve_rate(env: Env) -> i128 {
+        env.storage().instance().get(&RESERVE_RATE).unwrap_or(0)
+    }
+
+    /// Get the loss reserve held by this contract
+    pub fn reserve_balance(env: Env) -> i128 {
+        env.storage().instance().get(&RESERVE).unwrap_or(0)
+    }
+
+    fn set_reserve(env: &Env, reserve: i128) {
+        env.storage().instance().set(&RESERVE, &reserve);
+    }
+
+    /// Set the origination fee and the protocol share of interest (owner or treasurer)
+    pub fn set_fee_config(env: Env, caller: Address, config: FeeConfig) {
+        access_control::require_role(&env, Role::Treasurer, &caller);
+
+        if !(0..=10000).contains(&config.origination_fee_bps) {
+            panic!("Origination fee must be between 0 and 10000 basis points");
+        }
+        let reserve_rate = Self::get_reserve_rate(env.clone());
+        if config.protocol_fee_bps < 0 || config.protocol_fee_bps + reserve_rate > 10000 {
+            panic!("Reserve rate plus protocol fee must be between 0 and 10000 basis points");
+        }
+
+        env.storage().instance().set(&FEE_CFG, &config);
+    }
+
+    /// Get the fee configuration (no fees by default)
+    pub fn get_fee_config(env: Env) -> FeeConfig {
         env.storage()
             .instance()
-            .get(&symbol_short!("LOAN_ID"))
-            .unwrap_or(0)
+            .get(&FEE_CFG)
+            .unwrap_or(FeeConfig {
+                origination_fee_bps: 0,
+                protocol_fee_bps: 0,
+            })
     }
 
-    /// Increment loan ID
-    fn increment_loan_id(env: Env) {
-        let current: u32 = Self::get_next_loan_id(env.clone());
-        env.storage().instance().set(&symbol_short!("LOAN_ID"), &(current + 1));
+    /// Set the treasury, the only address fees can be withdrawn to (owner or admin)
+    /// Kept away from treasurers so whoever withdraws cannot also pick the destination.
+    pub fn set_treasury(env: Env, caller: Address, treasury: Address) {
+        access_control::require_role(&env, Role::Admin, &caller);
+        env.storage().instance().set(&TREASURY, &treasury);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use soroban_sdk::testutils::Address as _;
+    /// Get the treasury address
+    pub fn get_treasury(env: Env) -> Option<Address> {
+        env.storage().instance().get(&TREASURY)
+    }
 
-    #[test]
-    fn test_loan_manager_init() {
-        let env = Env::default();
-        let contract_id = env.register_contract(None, LoanManagerMicro);
-        let client = LoanManagerMicroClient::new(&env, &contract_id);
+    /// Get the total origination fees and interest fee share ever collected
+    pub fn get_fees_collected(env: Env) -> i128 {
+        env.storage().instance().get(&FEES).unwrap_or(0)
+    }
 
-        // Create addresses
-        let credit_scoring = Address::generate(&env);
-        let token = Address::generate(&env);
-        
-        // Initialize (lending_pool was removed from the system)
-        client.initialize(&credit_scoring, &token, &500);
+    /// Get the loan token balance the protocol can withdraw
+    /// Everything this contract holds except funds escrowed for lenders and borrowers and
+    /// the loss reserve: collected fees plus repayments of loans it funded by minting.
+    pub fn get_withdrawable_fees(env: Env) -> i128 {
+        let token_address = Self::get_token_address(&env);
+        let token_client = soroban_sdk::token::Client::new(&env, &token_address);
+        let balance = token_client.balance(&env.current_contract_address());
+
+        let withdrawable = balance
+            - Self::get_escrowed(env.clone(), token_address)
+            - Self::reserve_balance(env.clone());
+        withdrawable.max(0)
     }
-}
 
+    /// Send protocol funds to the treasury (owner or treasurer)
+    /// `to` must be the configured treasury, so a treasurer key cannot redirect funds.
+    pub fn withdraw_fees(env: Env, caller: Address, to: Address, amount: i128) {
+        access_control::require_role(&env, Role::Treasurer, &caller);
+
+        let treasury = Self::get_treasury(env.clone()).expect("Treasury not set");
+        if to != treasury {
+            panic!("Fees can only be withdrawn to the treasury");
+        }
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        if amount > Self::get_withdrawable_fees(env.clone()) {
+            panic!("Amount exceeds withdrawable fees");
+        }
+
+        let token_client = soroban_sdk::token::Client::new(&env, &Self::get_token_address(&env));
+        token_client.transfer(&env.current_contract_address(), &to, &amount);
+        Self::require_backed(&env);
+    }
+
+    /// Panic unless the loan token balance still covers the escrowed funds and the reserve
+    /// The reserve is only a label on that balance, so nothing else may spend into it.
+    fn require_backed(env: &Env) {
+        let token_address = Self::get_token_address(env);
+        let token_client = soroban_sdk::token::Client::new(env, &token_address);
+        let balance = token_client.balance(&env.current_contract_address());
+        let held = Self::get_escrowed(env.clone(), token_address)
+            + Self::reserve_balance(env.clone());
+        if balance < held {
+            panic!("Escrow and reserve are not backed by the contract balance");
+        }
+    }
+
+    /// Send principal held by this contract to a borrower, keeping the origination fee
+    fn send_principal(env: &Env, borrower: &Address, amount: i128) {
+        let fee = amount * Self::get_fee_config(env.clone()).origination_fee_bps / 10000;
+        Self::add_fees(env, fee);
+
+        let token_client = soroban_sdk::token::Client::new(env, &Self::get_token_address(env));
+        token_client.transfer(&env.current_contract_address(), borrower, &(amount - fee));
+    }
+
+    fn add_fees(env: &Env, fees: i128) {
+        if fees > 0 {
+            let collected = Self::get_fees_collected(env.clone()) + fees;
+            env.storage().instance().set(&FEES, &collected);
+        }
+    }
+
+    /// Refinance an active loan into a new one priced at the borrower's current rate
+    /// The new loan is funded like any protocol loan and its proceeds repay the unpaid
+    /// principal of the old one, which closes as repaid without forfeiting the interest of
+    /// its remaining installments to anyone. A minted loan rolled into another minted loan
+    /// is settled by netting, without minting or moving tokens. The old loan must be
+    /// current, and its unpaid principal must fit the borrower's limit (raised by any
+    /// collateral, which moves to the new loan). Circle, BNPL and guaranteed loans cannot be
+    /// refinanced. Returns the new loan id.
+    pub fn refinance(env: Env, loan_id: u32, new_num_payments: u32) -> u32 {
+        let loan_key = (symbol_short!("LOAN"), loan_id);
+        let mut loan = Self::get_loan(env.clone(), loan_id);
+        let borrower = loan.borrower.clone();
+        borrower.require_auth();
+
+        if loan.status != LoanStatus::Active {
+            panic!("Loan is not active");
+        }
+        if (env.ledger().timestamp() as i128) > Self::get_next_due_date(env.clone(), loan_id) {
+            panic!("Loan has an overdue installment");
+        }
+        // Their circle, purchase and guarantor are tied to the old loan id
+        if Self::get_loan_circle(&env, loan_id).is_some()
+            || Self::get_purchase(env.clone(), loan_id).is_some()
+            || Self::get_guarantee(env.clone(), loan_id).is_some()
+        {
+            panic!("Circle, BNPL and guaranteed loans cannot be refinanced");
+        }
+
+        let principal = Self::unpaid_principal(&env, loan_id, &loan);
+        let collateral = Self::get_collateral(env.clone(), loan_id);
+        let extra_limit = match (&collateral, Self::get_collateral_config(env.clone())) {
+            (Some(position), Some(config)) => {
+                Self::collateral_value(&env, &position.token, position.amount) * config.ltv_bps
+                    / 10000
+            }
+            _ => 0,
+        };
+        Self::check_borrower(&env, &borrower, principal, new_num_payments, extra_limit);
+
+        let interest_rate = Self::get_rate_for(env.clone(), borrower.clone());
+        let new_loan_id = if Self::get_lending_pool(env.clone()).is_none()
+            && Self::is_protocol_funded(&env, loan_id)
+        {
+            // Minting the new principal to repay the old loan would count it as protocol funds
+            Self::store_loan(&env, &borrower, principal, interest_rate, new_num_payments)
+        } else {
+            let contract = env.current_contract_address();
+            let new_loan_id = Self::fund_loan(
+                &env,
+                &borrower,
+                &contract,
+                principal,
+                interest_rate,
+                new_num_payments,
+            );
+            Self::pay_lenders_from_contract(&env, loan_id, principal, principal);
+            new_loan_id
+        };
+
+        let mut stats = Self::get_stats(env.clone());
+        stats.total_repaid += principal;
+        stats.outstanding_principal -= principal;
+        env.storage().instance().set(&STATS, &stats);
+
+        if let Some(position) = collateral {
+            env.storage()
+                .persistent()
+                .remove(&(symbol_short!("COLLAT"), loan_id));
+            env.storage()
+                .persistent()
+                .set(&(symbol_short!("COLLAT"), new_loan_id), &position);
+        }
+
+        loan.paid_payments = loan.num_payments;
+        Self::close_repaid_loan(&env, loan_id, &mut loan);
+        env.storage()
+            .persistent()
+            .set(&loan_key, &loan);
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("REFI"), loan_id), &new_loan_id);
+
+        new_loan_id
+    }
+
+    /// Get the loan a refinanced loan was rolled into, if any
+    pub fn get_refinanced_into(env: Env, loan_id: u32) -> Option<u32> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("REFI"), loan_id))
+    }
+
+    /// Replace the remaining schedule of an active loan (owner or risk manager, with the
+    /// consent of the borrower and of the loan's lenders)
+    /// The unpaid principal is re-amortized over `new_num_payments` installments at
+    /// `new_rate` (at most 10000 basis points), starting a new schedule from now. The loan's
+    /// `amount`, `num_payments` and `created_at` then describe the new schedule; the previous
+    /// terms stay in the modification history. The borrower's score takes a smaller penalty
+    /// than a default.
+    pub fn restructure_loan(
+        env: Env,
+        caller: Address,
+        loan_id: u32,
+        new_num_payments: u32,
+        new_rate: i128,
+    ) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+
+        let loan_key = (symbol_short!("LOAN"), loan_id);
+        let mut loan = Self::get_loan(env.clone(), loan_id);
+        loan.borrower.require_auth();
+        Self::require_lender_consent(&env, loan_id);
+
+        if loan.status != LoanStatus::Active {
+            panic!("Loan is not active");
+        }
+        if new_num_payments == 0 || new_num_payments > 12 {
+            panic!("Invalid number of payments");
+        }
+        if !(0..=10000).contains(&new_rate) {
+            panic!("Interest rate must be between 0 and 10000 basis points");
+        }
+
+        let debt_before = Self::remaining_debt(&env, loan_id, &loan);
+        let principal = Self::unpaid_principal(&env, loan_id, &loan);
+        let total_amount = principal + principal * new_rate / 10000;
+
+        loan.amount = principal;
+        loan.total_amount = total_amount;
+        loan.interest_rate = new_rate;
+        loan.num_payments = new_num_payments;
+        loan.paid_payments = 0;
+        loan.payment_amount = total_amount / new_num_payments as i128;
+        loan.created_at = env.ledger().timestamp() as i128;
+        env.storage()
+            .persistent()
+            .set(&loan_key, &loan);
+        env.storage()
+            .persistent()
+            .remove(&(symbol_short!("DEFERRED"), loan_id));
+        env.storage()
+            .persistent()
+            .remove(&(symbol_short!("SCHED"), loan_id));
+
+        Self::record_modification(
+            &env,
+            loan_id,
+            LoanModification {
+                kind: ModificationKind::Restructure,
+                approved_by: caller,
+                modified_at: env.ledger().timestamp(),
+                debt_before,
+                debt_after: Self::remaining_debt(&env, loan_id, &loan),
+                num_payments: new_num_payments,
+                interest_rate: new_rate,
+                periods: 0,
+            },
+        );
+        Self::penalize_borrower(env.clone(), loan.borrower, MODIFICATION_PENALTY);
+    }
+
+    /// Defer the remaining due dates of an active loan by `periods` payment periods
+    /// (owner or risk manager, with the consent of the borrower and of the loan's lenders)
+    /// The amounts owed do not change. The borrower's score takes a smaller penalty than
+    /// a default.
+    pub fn grant_forbearance(env: Env, caller: Address, loan_id: u32, periods: u32) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+
+        let loan = Self::get_loan(env.clone(), loan_id);
+        loan.borrower.require_auth();
+        Self::require_lender_consent(&env, loan_id);
+
+        if loan.status != LoanStatus::Active {
+            panic!("Loan is not active");
+        }
+        if periods == 0 || periods > 12 {
+            panic!("Invalid number of periods");
+        }
+
+        let deferred_key = (symbol_short!("DEFERRED"), loan_id);
+        let deferred: i128 = env.storage().persistent().get(&deferred_key).unwrap_or(0);
+        env.storage()
+            .persistent()
+            .set(&deferred_key, &(deferred + periods as i128 * PAYMENT_PERIOD));
+
+        let debt = Self::remaining_debt(&env, loan_id, &loan);
+        Self::record_modification(
+            &env,
+            loan_id,
+            LoanModification {
+                kind: ModificationKind::Forbearance,
+                approved_by: caller,
+                modified_at: env.ledger().timestamp(),
+                debt_before: debt,
+                debt_after: debt,
+                num_payments: loan.num_payments - loan.paid_payments,
+                interest_rate: loan.interest_rate,
+                periods,
+            },
+        );
+        Self::penalize_borrower(env.clone(), loan.borrower, MODIFICATION_PENALTY);
+    }
+
+    /// Require the auth of the P2P lender or of every lender of a request-funded loan, and of
+    /// the guarantor of a guaranteed one
+    /// Loans funded by the protocol or the pool are modified on the risk manager's say alone.
+    fn require_lender_consent(env: &Env, loan_id: u32) {
+        if let Some(lender) = Self::get_loan_lender(env.clone(), loan_id) {
+            lender.require_auth();
+        }
+        if let Some(guarantee) = Self::get_guarantee(env.clone(), loan_id) {
+            guarantee.guarantor.require_auth();
+        }
+        for (funder, _) in Self::get_loan_shares(env.clone(), loan_id).iter() {
+            funder.require_auth();
+        }
+    }
+
+    /// Get the modification history of a loan, oldest first
+    pub fn get_modifications(env: Env, loan_id: u32) -> Vec<LoanModification> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("MODS"), loan_id))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    fn record_modification(env: &Env, loan_id: u32, modification: LoanModification) {
+        let mut history = Self::get_modifications(env.clone(), loan_id);
+        history.push_back(modification);
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("MODS"), loan_id), &history);
+    }
+
+    /// Get the installments still owed on a loan
+    pub fn get_outstanding_debt(env: Env, loan_id: u32) -> i128 {
+        let loan = Self::get_loan(env.clone(), loan_id);
+        Self::remaining_debt(&env, loan_id, &loan)
+    }
+
+    /// Installments still owed on a loan
+    /// The last installment carries what is left after rounding down the others.
+    fn remaining_debt(env: &Env, loan_id: u32, loan: &Loan) -> i128 {
+        if loan.paid_payments >= loan.num_payments {
+            return 0;
+        }
+        let paid = loan.paid_payments - Self::schedule_start(env, loan_id);
+        loan.total_amount - loan.payment_amount * paid as i128
+    }
+
+    /// Amount due for the next `count` installments of a loan
+    fn installments_amount(env: &Env, loan_id: u32, loan: &Loan, count: u32) -> i128 {
+        if loan.paid_payments + count >= loan.num_payments {
+            return Self::remaining_debt(env, loan_id, loan);
+        }
+        loan.payment_amount * count as i128
+    }
+
+    /// Get the health factor of a secured loan in basis points
+    /// collateral value * liquidation threshold / outstanding debt; below 10000 the loan can
+    /// be liquidated.
+    pub fn get_health_factor(env: Env, loan_id: u32) -> i128 {
+        let loan = Self::get_loan(env.clone(), loan_id);
+        if Self::get_collateral(env.clone(), loan_id).is_none() {
+            panic!("Loan is not secured");
+        }
+
+        let debt = Self::remaining_debt(&env, loan_id, &loan);
+        if loan.status != LoanStatus::Active || debt == 0 {
+            return i128::MAX;
+        }
+
+        let config = Self::get_liquidation_config(env.clone());
+        Self::get_collateral_value(env, loan_id)
+            .checked_mul(config.threshold_bps)
+            .expect("Collateral value overflow")
+            / debt
+    }
+
+    /// Repay part of an under-collateralized secured loan in exchange for its collateral
+    /// Anyone can liquidate once the health factor is below 10000. `repay_amount` is rounded
+    /// down to whole installments and capped by the close factor; the liquidator receives
+    /// collateral worth the repaid amount plus the liquidation bonus, and the borrower's
+    /// score is penalized on the loan's first liquidation. Returns the collateral amount
+    /// seized.
+    pub fn liquidate(env: Env, liquidator: Address, loan_id: u32, repay_amount: i128) -> i128 {
+        liquidator.require_auth();
+        pause::require_not_paused(&env, PauseFlag::Repayments);
+
+        if repay_amount <= 0 {
+            panic!("Repay amount must be positive");
+        }
+
+        let loan_key = (symbol_short!("LOAN"), loan_id);
+        let mut loan = Self::get_loan(env.clone(), loan_id);
+
+        if loan.status != LoanStatus::Active {
+            panic!("Loan is not active");
+        }
+
+        let mut position = Self::get_collateral(env.clone(), loan_id)
+            .expect("Loan is not secured");
+
+        if Self::get_health_factor(env.clone(), loan_id) >= 10000 {
+            panic!("Loan is healthy");
+        }
+
+        let config = Self::get_liquidation_config(env.clone());
+        let remaining = loan.num_payments - loan.paid_payments;
+        let max_installments = (remaining as i128 * config.close_factor_bps / 10000).max(1);
+        let mut installments = (repay_amount / loan.payment_amount).min(max_installments) as u32;
+        if installments > 0
+            && Self::installments_amount(&env, loan_id, &loan, installments) > repay_amount
+        {
+            // The last installment also carries the rounding remainder
+            installments -= 1;
+        }
+        if installments == 0 {
+            panic!("Repay amount is below one installment");
+        }
+
+        // Value the collateral before the position shrinks
+        let collateral_value = Self::collateral_value(&env, &position.token, position.amount);
+
+        let repaid = Self::installments_amount(&env, loan_id, &loan, installments);
+        let principal_part = Self::record_installments(&env, loan_id, &mut loan, installments);
+        Self::pay_lenders(&env, loan_id, &liquidator, repaid, principal_part);
+
+        // Collateral worth repaid * (1 + bonus), capped at what is locked
+        let seized = repaid
+            .checked_mul(10000 + config.bonus_bps)
+            .and_then(|value| value.checked_mul(position.amount))
+            .expect("Collateral value overflow")
+            / (collateral_value.max(1) * 10000);
+        let seized = seized.min(position.amount);
+
+        position.amount -= seized;
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("COLLAT"), loan_id), &position);
+        Self::adjust_escrow(&env, &position.token, -seized);
+
+        let collateral_token = soroban_sdk::token::Client::new(&env, &position.token);
+        collateral_token.transfer(&env.current_contract_address(), &liquidator, &seized);
+
+        // A liquidation that covers the last installment closes the loan
+        if loan.paid_payments >= loan.num_payments {
+            Self::close_repaid_loan(&env, loan_id, &mut loan);
+        }
+        env.storage()
+            .persistent()
+            .set(&loan_key, &loan);
+
+        // Several partial liquidations of one loan only cost the borrower once
+        let penalized_key = (symbol_short!("LIQ_PEN"), loan_id);
+        if config.penalty > 0 && !env.storage().persistent().has(&penalized_key) {
+            env.storage().persistent().set(&penalized_key, &true);
+            Self::penalize_borrower(env.clone(), loan.borrower, config.penalty);
+        }
+
+        seized
+    }
+
+    /// Set the liquidation parameters (owner or risk manager)
+    pub fn set_liquidation_config(env: Env, caller: Address, config: LiquidationConfig) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+
+        if config.threshold_bps <= 0 || config.threshold_bps > 10000 {
+            panic!("Liquidation threshold must be between 1 and 10000 basis points");
+        }
+        if config.close_factor_bps <= 0 || config.close_factor_bps > 10000 {
+            panic!("Close factor must be between 1 and 10000 basis points");
+        }
+        if config.bonus_bps < 0 || config.bonus_bps > 10000 {
+            panic!("Liquidation bonus must be between 0 and 10000 basis points");
+        }
+        if config.penalty < 0 {
+            panic!("Penalty cannot be negative");
+        }
+
+        env.storage().instance().set(&LIQUIDATION_CFG, &config);
+    }
+
+    /// Get the liquidation parameters (defaults: 80% threshold, 50% close factor,
+    /// 5% bonus, 25 point penalty)
+    pub fn get_liquidation_config(env: Env) -> LiquidationConfig {
+        env.storage()
+            .instance()
+            .get(&LIQUIDATION_CFG)
+            .unwrap_or(LiquidationConfig {
+                threshold_bps: 8000,
+                close_factor_bps: 5000,
+                bonus_bps: 500,
+                penalty: 25,
+            })
+    }
+
+    /// Set the collateral token and loan-to-value ratio for secured loans (owner or risk manager)
+    pub fn set_collateral_config(env: Env, caller: Address, config: CollateralConfig) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+
+        if config.ltv_bps <= 0 || config.ltv_bps > 10000 {
+            panic!("Loan-to-value must be between 1 and 10000 basis points");
+        }
+
+        env.storage().instance().set(&COLLATERAL_CFG, &config);
+    }
+
+    /// Get the collateral configuration (None until secured loans are enabled)
+    pub fn get_collateral_config(env: Env) -> Option<CollateralConfig> {
+        env.storage().instance().get(&COLLATERAL_CFG)
+    }
+
+    /// Get the collateral locked for a loan (None for unsecured or closed loans)
+    pub fn get_collateral(env: Env, loan_id: u32) -> Option<CollateralPosition> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("COLLAT"), loan_id))
+    }
+
+    /// Get the total amount of a token held in escrow for open secured loans
+    pub fn get_escrowed(env: Env, token: Address) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("ESCROW"), token))
+            .unwrap_or(0)
+    }
+
+    /// Set the price oracle used to value collateral (owner or risk manager)
+    pub fn set_oracle_config(env: Env, caller: Address, config: OracleConfig) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+
+        if config.max_age == 0 {
+            panic!("Max price age must be positive");
+        }
+
+        env.storage().instance().set(&ORACLE_CFG, &config);
+    }
+
+    /// Get the price oracle configuration (None while collateral is valued at par)
+    pub fn get_oracle_config(env: Env) -> Option<OracleConfig> {
+        env.storage().instance().get(&ORACLE_CFG)
+    }
+
+    /// Get the current value of a loan's collateral in loan token units (0 if unsecured)
+    pub fn get_collateral_value(env: Env, loan_id: u32) -> i128 {
+        match Self::get_collateral(env.clone(), loan_id) {
+            Some(position) => Self::collateral_value(&env, &position.token, position.amount),
+            None => 0,
+        }
+    }
+
+    /// Value `amount` of a collateral token in loan token units
+    /// Both tokens are priced by the oracle, so the loan token does not need to be pegged.
+    fn collateral_value(env: &Env, token: &Address, amount: i128) -> i128 {
+        let Some(config) = Self::get_oracle_config(env.clone()) else {
+            return amount;
+        };
+
+        let loan_token = Self::get_token_address(env);
+        let collateral_price = Self::fresh_price(env, &config, token);
+        let loan_price = Self::fresh_price(env, &config, &loan_token);
+
+        // Rescale between the decimals of the two tokens
+        let collateral_decimals = soroban_sdk::token::Client::new(env, token).decimals();
+        let loan_decimals = soroban_sdk::token::Client::new(env, &loan_token).decimals();
+
+        amount
+            .checked_mul(collateral_price)
+            .and_then(|value| value.checked_mul(10i128.checked_pow(loan_decimals)?))
+            .and_then(|value| {
+                value.checked_div(loan_price.checked_mul(10i128.checked_pow(collateral_decimals)?)?)
+            })
+            .expect("Collateral value overflow")
+    }
+
+    /// Read a token price from the oracle, rejecting missing or stale prices
+    fn fresh_price(env: &Env, config: &OracleConfig, token: &Address) -> i128 {
+        let oracle = PriceOracleClient::new(env, &config.oracle);
+        let quote = oracle
+            .lastprice(&Asset::Stellar(token.clone()))
+            .expect("Price not available");
+
+        if env.ledger().timestamp().saturating_sub(quote.timestamp) > config.max_age {
+            panic!("Price is stale");
+        }
+        if quote.price <= 0 {
+            panic!("Invalid price");
+        }
+
+        quote.price
+    }
+
+    /// Add `delta` (negative to subtract) to the escrow total of a token
+    fn adjust_escrow(env: &Env, token: &Address, delta: i128) {
+        let escrowed = Self::get_escrowed(env.clone(), token.clone()) + delta;
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("ESCROW"), token.clone()), &escrowed);
+    }
+
+    /// Return the collateral of a repaid loan to the borrower
+    fn release_collateral(env: &Env, loan_id: u32, borrower: &Address) {
+        let Some(position) = Self::get_collateral(env.clone(), loan_id) else {
+            return;
+        };
+
+        env.storage()
+            .persistent()
+            .remove(&(symbol_short!("COLLAT"), loan_id));
+        Self::adjust_escrow(env, &position.token, -position.amount);
+
+        let collateral_token = soroban_sdk::token::Client::new(env, &position.token);
+        collateral_token.transfer(
+            &env.current_contract_address(),
+            borrower,
+            &position.amount,
+        );
+    }
+
+    /// Move the collateral of a defaulted loan out of escrow to whoever funded the loan
+    /// The pool, the P2P lender or the request funders (pro rata) receive it; for loans
+    /// the protocol minted the tokens stay on this contract for the treasury to withdraw.
+    fn forfeit_collateral(env: &Env, loan_id: u32) {
+        let Some(position) = Self::get_collateral(env.clone(), loan_id) else {
+            return;
+        };
+
+        env.storage()
+            .persistent()
+            .remove(&(symbol_short!("COLLAT"), loan_id));
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("FORFEIT"), loan_id), &position);
+        Self::adjust_escrow(env, &position.token, -position.amount);
+
+        let contract = env.current_contract_address();
+        let collateral_token = soroban_sdk::token::Client::new(env, &position.token);
+        let shares = Self::get_loan_shares(env.clone(), loan_id);
+        if let Some(pool) = Self::get_loan_pool(env, loan_id) {
+            collateral_token.transfer(&contract, &pool, &position.amount);
+        } else if let Some(lender) = Self::get_loan_lender(env.clone(), loan_id) {
+            collateral_token.transfer(&contract, &lender, &position.amount);
+        } else if !shares.is_empty() {
+            Self::pay_shares(&collateral_token, &contract, &shares, position.amount);
+        }
+    }
+
+    /// Get the collateral a defaulted loan forfeited to its funders, if any
+    pub fn get_forfeited(env: Env, loan_id: u32) -> Option<CollateralPosition> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("FORFEIT"), loan_id))
+    }
+
+    /// Send collateral forfeited on protocol loans to the treasury (owner or treasurer)
+    /// Only what is not escrowed for open loans can leave; the loan token goes through
+    /// withdraw_fees, which also keeps the loss reserve.
+    pub fn withdraw_forfeited(env: Env, caller: Address, token: Address, amount: i128) {
+        access_control::require_role(&env, Role::Treasurer, &caller);
+
+        if token == Self::get_token_address(&env) {
+            panic!("Use withdraw_fees for the loan token");
+        }
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        let treasury = Self::get_treasury(env.clone()).expect("Treasury not set");
+        let token_client = soroban_sdk::token::Client::new(&env, &token);
+        let contract = env.current_contract_address();
+        let free = token_client.balance(&contract) - Self::get_escrowed(env.clone(), token.clone());
+        if amount > free {
+            panic!("Amount exceeds forfeited collateral");
+        }
+
+        token_client.transfer(&contract, &treasury, &amount);
+    }
+
+    /// Helper to get a user's verification level from the identity registry
+    fn verification_level(env: &Env, user: &Address) -> VerificationLevel {
+        let identity_registry: Address = env
+            .storage()
+            .instance()
+            .get(&IDENTITY)
+            .expect("Identity registry not set");
+        env.invoke_contract(
+            &identity_registry,
+            &Symbol::new(env, "get_verification_level"),
+            soroban_sdk::vec![env, user.clone().into_val(env)],
+        )
+    }
+
+    /// Form a lending circle of 3 to 10 verified borrowers (every member signs)
+    /// When a member's circle loan defaults, the others owe `liability_bps` of its unpaid
+    /// principal between them. Returns the circle id.
+    pub fn form_circle(env: Env, members: Vec<Address>, liability_bps: i128) -> u32 {
+        pause::require_not_paused(&env, PauseFlag::NewLoans);
+
+        if members.len() < 3 || members.len() > 10 {
+            panic!("A circle needs 3 to 10 members");
+        }
+        if !(0..=10000).contains(&liability_bps) {
+            panic!("Liability must be between 0 and 10000 basis points");
+        }
+
+        let circle_id: u32 = env.storage().instance().get(&CIRCLE_ID).unwrap_or(0);
+        for (i, member) in members.iter().enumerate() {
+            member.require_auth();
+            if members.first_index_of(&member) != Some(i as u32) {
+                panic!("Duplicate member");
+            }
+            if Self::get_member_circle(env.clone(), member.clone()).is_some() {
+                panic!("Member already in a circle");
+            }
+            if Self::verification_level(&env, &member) == VerificationLevel::Unverified {
+                panic!("Member identity not verified");
+            }
+            env.storage()
+                .persistent()
+                .set(&(symbol_short!("MEMBER"), member), &circle_id);
+        }
+
+        let circle = LendingCircle {
+            members,
+            liability_bps,
+            score: CIRCLE_INITIAL_SCORE,
+            repaid_count: 0,
+            defaulted_count: 0,
+            active_loans: 0,
+            open_obligations: 0,
+        };
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("CIRCLE"), circle_id), &circle);
+        env.storage().instance().set(&CIRCLE_ID, &(circle_id + 1));
+
+        circle_id
+    }
+
+    /// Get a lending circle by id
+    pub fn get_circle(env: Env, circle_id: u32) -> LendingCircle {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("CIRCLE"), circle_id))
+            .expect("Circle not found")
+    }
+
+    /// Get the number of circles formed (ids are `0..total`)
+    pub fn get_total_circles(env: Env) -> u32 {
+        env.storage().instance().get(&CIRCLE_ID).unwrap_or(0)
+    }
+
+    /// Get the circle a borrower belongs to, if any
+    pub fn get_member_circle(env: Env, member: Address) -> Option<u32> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("MEMBER"), member))
+    }
+
+    /// Get a member's limit for circle loans
+    /// Their own available limit scaled by the circle's collective score: 1x for a new circle,
+    /// up to 2x after enough repaid circle loans, and down to nothing after unmet joint
+    /// liabilities.
+    pub fn get_circle_limit(env: Env, member: Address) -> i128 {
+        let circle_id =
+            Self::get_member_circle(env.clone(), member.clone()).expect("Not in a circle");
+        let circle = Self::get_circle(env.clone(), circle_id);
+        let score = circle.score.clamp(0, CIRCLE_MAX_SCORE);
+        Self::available_limit(&env, &member) * score / CIRCLE_INITIAL_SCORE
+    }
+
+    /// Request a loan backed by the borrower's circle, limited by `get_circle_limit`
+    pub fn request_circle_loan(
+        env: Env,
+        borrower: Address,
+        amount: i128,
+        num_payments: u32,
+    ) -> u32 {
+        borrower.require_auth();
+
+        let circle_id =
+            Self::get_member_circle(env.clone(), borrower.clone()).expect("Not in a circle");
+        let extra_limit = Self::get_circle_limit(env.clone(), borrower.clone())
+            - Self::available_limit(&env, &borrower);
+        let loan_id = Self::open_loan(env.clone(), borrower, amount, num_payments, extra_limit);
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("CIRC_LN"), loan_id), &circle_id);
+
+        let mut circle = Self::get_circle(env.clone(), circle_id);
+        circle.active_loans += 1;
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("CIRCLE"), circle_id), &circle);
+        loan_id
+    }
+
+    /// Leave a lending circle (the member signs)
+    /// Only while the circle has no active loans or open joint obligations. A circle left
+    /// with fewer than 3 members is dissolved.
+    pub fn leave_circle(env: Env, member: Address) {
+        member.require_auth();
+
+        let circle_id =
+            Self::get_member_circle(env.clone(), member.clone()).expect("Not in a circle");
+        let mut circle = Self::get_circle(env.clone(), circle_id);
+        Self::require_circle_settled(&circle);
+
+        let index = circle.members.first_index_of(&member).expect("Not in a circle");
+        circle.members.remove(index);
+        env.storage()
+            .persistent()
+            .remove(&(symbol_short!("MEMBER"), member));
+        if circle.members.len() < 3 {
+            Self::clear_circle_members(&env, &mut circle);
+        }
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("CIRCLE"), circle_id), &circle);
+    }
+
+    /// Dissolve a lending circle (every member signs)
+    /// Only while the circle has no active loans or open joint obligations. The circle keeps
+    /// its history but has no members left, who are free to join another one.
+    pub fn dissolve_circle(env: Env, circle_id: u32) {
+        let mut circle = Self::get_circle(env.clone(), circle_id);
+        if circle.members.is_empty() {
+            panic!("Circle already dissolved");
+        }
+        for member in circle.members.iter() {
+            member.require_auth();
+        }
+        Self::require_circle_settled(&circle);
+
+        Self::clear_circle_members(&env, &mut circle);
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("CIRCLE"), circle_id), &circle);
+    }
+
+    /// Panic while a circle still has loans or joint obligations its members answer for
+    fn require_circle_settled(circle: &LendingCircle) {
+        if circle.active_loans > 0 || circle.open_obligations > 0 {
+            panic!("Circle has active loans or open obligations");
+        }
+    }
+
+    /// Remove every member from a circle so they can join another one
+    fn clear_circle_members(env: &Env, circle: &mut LendingCircle) {
+        for member in circle.members.iter() {
+            env.storage()
+                .persistent()
+                .remove(&(symbol_short!("MEMBER"), member));
+        }
+        circle.members = Vec::new(env);
+    }
+
+    /// Circle backing a loan, if any
+    fn get_loan_circle(env: &Env, loan_id: u32) -> Option<u32> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("CIRC_LN"), loan_id))
+    }
+
+    /// Credit the circle of a repaid circle loan
+    fn circle_loan_repaid(env: &Env, loan_id: u32) {
+        let Some(circle_id) = Self::get_loan_circle(env, loan_id) else {
+            return;
+        };
+        let mut circle = Self::get_circle(env.clone(), circle_id);
+        circle.score += CIRCLE_REWARD;
+        circle.repaid_count += 1;
+        circle.active_loans -= 1;
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("CIRCLE"), circle_id), &circle);
+    }
+
+    /// Split the joint liability for a defaulted circle loan among the other members
+    /// They have one payment period to pay it before the obligation can be settled.
+    fn open_joint_obligation(env: &Env, loan_id: u32, borrower: &Address, unpaid: i128) {
+        let Some(circle_id) = Self::get_loan_circle(env, loan_id) else {
+            return;
+        };
+        let mut circle = Self::get_circle(env.clone(), circle_id);
+        circle.defaulted_count += 1;
+        circle.active_loans -= 1;
+        circle.open_obligations += 1;
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("CIRCLE"), circle_id), &circle);
+
+        let total = unpaid * circle.liability_bps / 10000;
+        let others = circle.members.len() - 1;
+        let mut owed = Map::new(env);
+        let mut assigned = 0;
+        for member in circle.members.iter() {
+            if member == *borrower {
+                continue;
+            }
+            // The rounding remainder goes to the last member
+            let share = if owed.len() + 1 == others {
+                total - assigned
+            } else {
+                total / others as i128
+            };
+            owed.set(member, share);
+            assigned += share;
+        }
+
+        env.storage().persistent().set(
+            &(symbol_short!("OBLIG"), loan_id),
+            &JointObligation {
+                circle_id,
+                owed,
+                recovered: 0,
+                deadline: env.ledger().timestamp() + PAYMENT_PERIOD as u64,
+                settled: false,
+            },
+        );
+    }
+
+    /// Pay a member's share of the joint liability for a defaulted circle loan
+    /// The payment goes to the loan's lenders as recovered principal. Returns the amount paid.
+    pub fn pay_obligation(env: Env, member: Address, loan_id: u32) -> i128 {
+        member.require_auth();
+        pause::require_not_paused(&env, PauseFlag::Repayments);
+
+        let mut obligation =
+            Self::get_joint_obligation(env.clone(), loan_id).expect("No joint obligation");
+        if obligation.settled {
+            panic!("Joint obligation already settled");
+        }
+        let share = obligation.owed.get(member.clone()).unwrap_or(0);
+        if share == 0 {
+            panic!("Nothing owed");
+        }
+
+        Self::pay_lenders(&env, loan_id, &member, share, share);
+        let mut stats = Self::get_stats(env.clone());
+        stats.total_repaid += share;
+        env.storage().instance().set(&STATS, &stats);
+
+        obligation.owed.set(member, 0);
+        obligation.recovered += share;
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("OBLIG"), loan_id), &obligation);
+        share
+    }
+
+    /// Settle the joint liability for a defaulted circle loan
+    /// Anyone can call this once every member has paid or the deadline has passed. If any
+    /// share went unpaid the circle's collective score is penalized.
+    pub fn settle_obligation(env: Env, loan_id: u32) {
+        let mut obligation =
+            Self::get_joint_obligation(env.clone(), loan_id).expect("No joint obligation");
+        if obligation.settled {
+            panic!("Joint obligation already settled");
+        }
+
+        let unpaid = obligation.owed.values().iter().any(|owed| owed > 0);
+        if unpaid && env.ledger().timestamp() <= obligation.deadline {
+            panic!("Joint obligation is still open");
+        }
+
+        let mut circle = Self::get_circle(env.clone(), obligation.circle_id);
+        circle.open_obligations -= 1;
+        if unpaid {
+            circle.score -= CIRCLE_PENALTY;
+        }
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("CIRCLE"), obligation.circle_id), &circle);
+
+        obligation.settled = true;
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("OBLIG"), loan_id), &obligation);
+    }
+
+    /// Get the joint obligation of a defaulted circle loan, if any
+    pub fn get_joint_obligation(env: Env, loan_id: u32) -> Option<JointObligation> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("OBLIG"), loan_id))
+    }
+
+    /// Register a merchant partner for "buy now, pay later" purchases (owner or risk manager,
+    /// with the merchant's consent to the discount rate)
+    /// Registering again updates the merchant's name and discount.
+    pub fn register_merchant(
+        env: Env,
+        caller: Address,
+        merchant: Address,
+        name: String,
+        discount_bps: i128,
+    ) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+        merchant.require_auth();
+
+        if !(0..10000).contains(&discount_bps) {
+            panic!("Discount must be between 0 and 9999 basis points");
+        }
+
+        env.storage().persistent().set(
+            &(symbol_short!("MERCHANT"), merchant),
+            &Merchant { name, discount_bps },
+        );
+    }
+
+    /// Remove a merchant partner (owner or risk manager)
+    /// Purchases already made can still be refunded by the merchant.
+    pub fn remove_merchant(env: Env, caller: Address, merchant: Address) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+        env.storage()
+            .persistent()
+            .remove(&(symbol_short!("MERCHANT"), merchant));
+    }
+
+    /// Get a merchant partner, if registered
+    pub fn get_merchant(env: Env, merchant: Address) -> Option<Merchant> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("MERCHANT"), merchant))
+    }
+
+    /// Pay a registered merchant at checkout with a zero-interest loan of `installments`
+    /// The loan is funded like any protocol loan and disbursed to the merchant minus its
+    /// discount, which goes to the loan's funder in place of interest. Returns the loan id.
+    pub fn bnpl_purchase(
+        env: Env,
+        borrower: Address,
+        merchant: Address,
+        amount: i128,
+        installments: u32,
+    ) -> u32 {
+        borrower.require_auth();
+
+        let config = Self::get_merchant(env.clone(), merchant.clone())
+            .expect("Merchant not registered");
+        if merchant == borrower {
+            panic!("Cannot buy from yourself");
+        }
+        Self::check_borrower(&env, &borrower, amount, installments, 0);
+
+        let contract = env.current_contract_address();
+        let loan_id = Self::fund_loan(&env, &borrower, &contract, amount, 0, installments);
+
+        let discount = amount * config.discount_bps / 10000;
+        let token_client = soroban_sdk::token::Client::new(&env, &Self::get_token_address(&env));
+        token_client.transfer(&contract, &merchant, &(amount - discount));
+        if discount > 0 {
+            Self::pay_lenders_from_contract(&env, loan_id, discount, 0);
+        }
+
+        env.storage().persistent().set(
+            &(symbol_short!("BNPL"), loan_id),
+            &BnplPurchase {
+                merchant,
+                amount,
+                refunded: 0,
+            },
+        );
+        loan_id
+    }
+
+    /// Refund `amount` of a purchase (the merchant of the purchase)
+    /// The refund first pays what the borrower still owes to the loan's lenders; what is
+    /// left over, up to the installments already paid, goes back to the borrower. Refunds
+    /// add up to at most the purchase amount. A refund that covers the debt closes the
+    /// loan; otherwise the rest is spread over the remaining installments, the last one
+    /// carrying the rounding remainder, without moving due dates or the installments
+    /// already paid. The discount is not returned.
+    pub fn refund_purchase(env: Env, merchant: Address, loan_id: u32, amount: i128) {
+        merchant.require_auth();
+        pause::require_not_paused(&env, PauseFlag::Repayments);
+
+        let purchase_key = (symbol_short!("BNPL"), loan_id);
+        let mut purchase: BnplPurchase = env
+            .storage()
+            .persistent()
+            .get(&purchase_key)
+            .expect("Loan is not a purchase");
+        if purchase.merchant != merchant {
+            panic!("Not your purchase");
+        }
+        if amount <= 0 || amount > purchase.amount - purchase.refunded {
+            panic!("Refund must be positive and at most what is left of the purchase");
+        }
+
+        let loan_key = (symbol_short!("LOAN"), loan_id);
+        let mut loan = Self::get_loan(env.clone(), loan_id);
+        let debt = match loan.status {
+            LoanStatus::Active => Self::remaining_debt(&env, loan_id, &loan),
+            LoanStatus::Repaid => 0,
+            _ => panic!("Loan is not active"),
+        };
+
+        let to_lenders = amount.min(debt);
+        if to_lenders > 0 {
+            let principal = Self::unpaid_principal(&env, loan_id, &loan);
+            Self::pay_lenders(&env, loan_id, &merchant, to_lenders, to_lenders);
+
+            if to_lenders == debt {
+                loan.paid_payments = loan.num_payments;
+                Self::close_repaid_loan(&env, loan_id, &mut loan);
+            } else {
+                // The installments left become a schedule of their own
+                let remaining_payments = loan.num_payments - loan.paid_payments;
+                loan.amount = debt - to_lenders;
+                loan.total_amount = loan.amount;
+                loan.payment_amount = loan.amount / remaining_payments as i128;
+                env.storage()
+                    .persistent()
+                    .set(&(symbol_short!("SCHED"), loan_id), &loan.paid_payments);
+            }
+
+            let mut stats = Self::get_stats(env.clone());
+            stats.total_repaid += to_lenders;
+            stats.outstanding_principal -= principal - Self::unpaid_principal(&env, loan_id, &loan);
+            env.storage().instance().set(&STATS, &stats);
+            env.storage()
+                .persistent()
+                .set(&loan_key, &loan);
+        }
+
+        if amount > to_lenders {
+            let token_client =
+                soroban_sdk::token::Client::new(&env, &Self::get_token_address(&env));
+            token_client.transfer(&merchant, &loan.borrower, &(amount - to_lenders));
+        }
+
+        purchase.refunded += amount;
+        env.storage().persistent().set(&purchase_key, &purchase);
+    }
+
+    /// Get the merchant and refunds of a loan opened by `bnpl_purchase`
+    pub fn get_purchase(env: Env, loan_id: u32) -> Option<BnplPurchase> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("BNPL"), loan_id))
+    }
+
+    /// Set the identity registry used to check verification levels of offer takers
+    /// (owner or risk manager)
+    pub fn set_identity_registry(env: Env, caller: Address, identity_registry: Address) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+        env.storage().instance().set(&IDENTITY, &identity_registry);
+    }
+
+    /// Get the identity registry used to check verification levels
+    pub fn get_identity_registry(env: Env) -> Option<Address> {
+        env.storage().instance().get(&IDENTITY)
+    }
+
+    /// Post a loan offer funded from the lender's balance
+    /// The principal is escrowed in this contract until a borrower accepts the offer or the
+    /// lender cancels it.
+    pub fn create_offer(
+        env: Env,
+        lender: Address,
+        amount: i128,
+        interest_rate: i128,
+        num_payments: u32,
+        min_score: i128,
+        min_level: VerificationLevel,
+    ) -> u32 {
+        lender.require_auth();
+        pause::require_not_paused(&env, PauseFlag::NewLoans);
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        if num_payments == 0 || num_payments > 12 {
+            panic!("Invalid number of payments");
+        }
+        if interest_rate < 0 {
+            panic!("Interest rate cannot be negative");
+        }
+
+        let token_address = Self::get_token_address(&env);
+        let token_client = soroban_sdk::token::Client::new(&env, &token_address);
+        token_client.transfer(&lender, env.current_contract_address(), &amount);
+        Self::adjust_escrow(&env, &token_address, amount);
+
+        let offer_id: u32 = env.storage().instance().get(&OFFER_ID).unwrap_or(0);
+        env.storage().instance().set(&OFFER_ID, &(offer_id + 1));
+
+        let offer = LoanOffer {
+            lender,
+            amount,
+            interest_rate,
+            num_payments,
+            min_score,
+            min_level,
+            status: OfferStatus::Open,
+            loan_id: None,
+        };
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("OFFER"), offer_id), &offer);
+
+        offer_id
+    }
+
+    /// Accept an open offer: the escrowed principal is sent to the borrower and the
+    /// installments are paid to the lender
+    /// The borrower must meet the offer's minimum score and verification level, and the
+    /// amount must fit their credit limit.
+    pub fn accept_offer(env: Env, borrower: Address, offer_id: u32) -> u32 {
+        borrower.require_auth();
+
+        let offer_key = (symbol_short!("OFFER"), offer_id);
+        let mut offer = Self::get_offer(env.clone(), offer_id);
+
+        if offer.status != OfferStatus::Open {
+            panic!("Offer is not open");
+        }
+        if offer.lender == borrower {
+            panic!("Cannot accept your own offer");
+        }
+
+        Self::check_borrower(&env, &borrower, offer.amount, offer.num_payments, 0);
+
+        if Self::get_score(&env, &borrower) < offer.min_score {
+            panic!("Score below offer minimum");
+        }
+
+        if offer.min_level > VerificationLevel::Unverified
+            && Self::verification_level(&env, &borrower) < offer.min_level
+        {
+            panic!("Verification level below offer minimum");
+        }
+
+        let token_address = Self::get_token_address(&env);
+        Self::adjust_escrow(&env, &token_address, -offer.amount);
+        Self::send_principal(&env, &borrower, offer.amount);
+
+        let loan_id = Self::store_loan(
+            &env,
+            &borrower,
+            offer.amount,
+            offer.interest_rate,
+            offer.num_payments,
+        );
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("LENDER"), loan_id), &offer.lender);
+
+        offer.status = OfferStatus::Filled;
+        offer.loan_id = Some(loan_id);
+        env.storage().persistent().set(&offer_key, &offer);
+
+        loan_id
+    }
+
+    /// Cancel an unfilled offer and return the escrowed principal to the lender
+    pub fn cancel_offer(env: Env, lender: Address, offer_id: u32) {
+        lender.require_auth();
+
+        let offer_key = (symbol_short!("OFFER"), offer_id);
+        let mut offer = Self::get_offer(env.clone(), offer_id);
+
+        if offer.lender != lender {
+            panic!("Not your offer");
+        }
+        if offer.status != OfferStatus::Open {
+            panic!("Offer is not open");
+        }
+
+        let token_address = Self::get_token_address(&env);
+        Self::adjust_escrow(&env, &token_address, -offer.amount);
+        let token_client = soroban_sdk::token::Client::new(&env, &token_address);
+        token_client.transfer(&env.current_contract_address(), &lender, &offer.amount);
+
+        offer.status = OfferStatus::Cancelled;
+        env.storage().persistent().set(&offer_key, &offer);
+    }
+
+    /// Get a loan offer
+    pub fn get_offer(env: Env, offer_id: u32) -> LoanOffer {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("OFFER"), offer_id))
+            .expect("Offer not found")
+    }
+
+    /// Get total number of offers ever posted (offer ids are 0..get_total_offers())
+    pub fn get_total_offers(env: Env) -> u32 {
+        env.storage().instance().get(&OFFER_ID).unwrap_or(0)
+    }
+
+    /// Get the lender of a P2P loan (None for loans funded by the protocol)
+    pub fn get_loan_lender(env: Env, loan_id: u32) -> Option<Address> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("LENDER"), loan_id))
+    }
+
+    /// Publish a loan request for lenders to fund before `deadline`
+    /// The loan is created right away as `Pending`, becomes `Active` once the request is
+    /// fully funded and `Cancelled` if it never is.
+    pub fn post_request(
+        env: Env,
+        borrower: Address,
+        amount: i128,
+        max_rate: i128,
+        num_payments: u32,
+        purpose_cid: String,
+        deadline: u64,
+    ) -> u32 {
+        borrower.require_auth();
+        Self::check_borrower(&env, &borrower, amount, num_payments, 0);
+
+        if max_rate < 0 {
+            panic!("Interest rate cannot be negative");
+        }
+        if deadline <= env.ledger().timestamp() {
+            panic!("Deadline must be in the future");
+        }
+
+        let request_id: u32 = env.storage().instance().get(&REQUEST_ID).unwrap_or(0);
+        env.storage().instance().set(&REQUEST_ID, &(request_id + 1));
+
+        let loan_id = Self::create_loan(
+            &env,
+            &borrower,
+            amount,
+            max_rate,
+            num_payments,
+            LoanStatus::Pending,
+        );
+        let request = LoanRequest {
+            borrower,
+            amount,
+            max_rate,
+            num_payments,
+            purpose_cid,
+            deadline,
+            funded: 0,
+            status: RequestStatus::Open,
+            loan_id: Some(loan_id),
+        };
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("REQUEST"), request_id), &request);
+
+        request_id
+    }
+
+    /// Commit funds to an open request; the amount is capped at what is still missing
+    /// The funds are escrowed in this contract. The contribution that completes the request
+    /// disburses the loan at the posted `max_rate` (there is no bidding on the rate), and its
+    /// installments are then split pro-rata between the lenders. If the borrower is no longer
+    /// eligible by then (blacklisted or over their limit), the request is cancelled and every
+    /// lender is refunded instead. Returns the amount actually committed.
+    pub fn fund_request(env: Env, lender: Address, request_id: u32, amount: i128) -> i128 {
+        lender.require_auth();
+        pause::require_not_paused(&env, PauseFlag::NewLoans);
+
+        let request_key = (symbol_short!("REQUEST"), request_id);
+        let mut request = Self::get_request(env.clone(), request_id);
+
+        if request.status != RequestStatus::Open {
+            panic!("Request is not open");
+        }
+        if env.ledger().timestamp() > request.deadline {
+            panic!("Funding deadline has passed");
+        }
+        if lender == request.borrower {
+            panic!("Cannot fund your own request");
+        }
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        let mut committed = amount.min(request.amount - request.funded);
+
+        let token_address = Self::get_token_address(&env);
+        let token_client = soroban_sdk::token::Client::new(&env, &token_address);
+        token_client.transfer(&lender, env.current_contract_address(), &committed);
+        Self::adjust_escrow(&env, &token_address, committed);
+
+        let mut funders = Self::get_request_funders(env.clone(), request_id);
+        let previous = funders.get(lender.clone()).unwrap_or(0);
+        funders.set(lender, previous + committed);
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("FUNDERS"), request_id), &funders);
+
+        request.funded += committed;
+        if request.funded == request.amount {
+            if Self::can_borrow(&env, &request.borrower, request.amount) {
+                Self::disburse_request(&env, &mut request, funders);
+            } else {
+                Self::refund_request(&env, request_id, &mut request, funders);
+                committed = 0;
+            }
+        }
+        env.storage().persistent().set(&request_key, &request);
+
+        committed
+    }
+
+    /// Whether the borrower of a fully funded request can still take the loan
+    fn can_borrow(env: &Env, borrower: &Address, amount: i128) -> bool {
+        if Self::is_blacklisted(env, borrower) {
+            return false;
+        }
+        amount <= Self::available_limit(env, borrower)
+    }
+
+    /// Cancel a fully funded request and return every lender's contribution
+    fn refund_request(
+        env: &Env,
+        request_id: u32,
+        request: &mut LoanRequest,
+        funders: Map<Address, i128>,
+    ) {
+        let token_address = Self::get_token_address(env);
+        let token_client = soroban_sdk::token::Client::new(env, &token_address);
+        for (funder, contribution) in funders.iter() {
+            token_client.transfer(&env.current_contract_address(), &funder, &contribution);
+        }
+        Self::adjust_escrow(env, &token_address, -request.funded);
+        env.storage()
+            .persistent()
+            .remove(&(symbol_short!("FUNDERS"), request_id));
+
+        request.funded = 0;
+        request.status = RequestStatus::Cancelled;
+        Self::cancel_pending_loan(env, request);
+    }
+
+    /// Cancel the pending loan of a request that will not be disbursed
+    fn cancel_pending_loan(env: &Env, request: &LoanRequest) {
+        let Some(loan_id) = request.loan_id else {
+            return;
+        };
+        let mut loan = Self::get_loan(env.clone(), loan_id);
+        if loan.status == LoanStatus::Pending {
+            loan.status = LoanStatus::Cancelled;
+            env.storage()
+                .persistent()
+                .set(&(symbol_short!("LOAN"), loan_id), &loan);
+        }
+    }
+
+    /// Disburse a fully funded request to its borrower
+    fn disburse_request(env: &Env, request: &mut LoanRequest, funders: Map<Address, i128>) {
+        let token_address = Self::get_token_address(env);
+        Self::adjust_escrow(env, &token_address, -request.amount);
+        Self::send_principal(env, &request.borrower, request.amount);
+
+        // Requests posted before loans were created as pending have no loan yet
+        let loan_id = match request.loan_id {
+            Some(loan_id) => {
+                let mut loan = Self::get_loan(env.clone(), loan_id);
+                loan.status = LoanStatus::Active;
+                loan.created_at = env.ledger().timestamp() as i128;
+                env.storage()
+                    .persistent()
+                    .set(&(symbol_short!("LOAN"), loan_id), &loan);
+                Self::record_disbursal(env, loan_id, &request.borrower, request.amount);
+                loan_id
+            }
+            None => Self::store_loan(
+                env,
+                &request.borrower,
+                request.amount,
+                request.max_rate,
+                request.num_payments,
+            ),
+        };
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("SHARES"), loan_id), &funders);
+
+        request.status = RequestStatus::Disbursed;
+        request.loan_id = Some(loan_id);
+    }
+
+    /// Withdraw an open request (only the borrower); lenders can then reclaim their funds
+    pub fn cancel_request(env: Env, borrower: Address, request_id: u32) {
+        borrower.require_auth();
+
+        let request_key = (symbol_short!("REQUEST"), request_id);
+        let mut request = Self::get_request(env.clone(), request_id);
+
+        if request.borrower != borrower {
+            panic!("Not your request");
+        }
+        if request.status != RequestStatus::Open {
+            panic!("Request is not open");
+        }
+
+        request.status = RequestStatus::Cancelled;
+        env.storage().persistent().set(&request_key, &request);
+        Self::cancel_pending_loan(&env, &request);
+    }
+
+    /// Return a lender's contribution to a request that was cancelled or missed its deadline
+    pub fn reclaim_funding(env: Env, lender: Address, request_id: u32) -> i128 {
+        lender.require_auth();
+
+        let request_key = (symbol_short!("REQUEST"), request_id);
+        let mut request = Self::get_request(env.clone(), request_id);
+
+        let expired = request.status == RequestStatus::Open
+            && env.ledger().timestamp() > request.deadline;
+        if request.status != RequestStatus::Cancelled && !expired {
+            panic!("Request funding cannot be reclaimed");
+        }
+        Self::cancel_pending_loan(&env, &request);
+
+        let mut funders = Self::get_request_funders(env.clone(), request_id);
+        let contribution = funders.get(lender.clone()).unwrap_or(0);
+        if contribution == 0 {
+            panic!("Nothing to reclaim");
+        }
+        funders.remove(lender.clone());
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("FUNDERS"), request_id), &funders);
+
+        request.funded -= contribution;
+        env.storage().persistent().set(&request_key, &request);
+
+        let token_address = Self::get_token_address(&env);
+        Self::adjust_escrow(&env, &token_address, -contribution);
+        let token_client = soroban_sdk::token::Client::new(&env, &token_address);
+        token_client.transfer(&env.current_contract_address(), &lender, &contribution);
+
+        contribution
+    }
+
+    /// Get a borrower-posted loan request
+    pub fn get_request(env: Env, request_id: u32) -> LoanRequest {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("REQUEST"), request_id))
+            .expect("Request not found")
+    }
+
+    /// Get total number of requests ever posted (request ids are 0..get_total_requests())
+    pub fn get_total_requests(env: Env) -> u32 {
+        env.storage().instance().get(&REQUEST_ID).unwrap_or(0)
+    }
+
+    /// Get the amount each lender has committed to a request
+    pub fn get_request_funders(env: Env, request_id: u32) -> Map<Address, i128> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("FUNDERS"), request_id))
+            .unwrap_or(Map::new(&env))
+    }
+
+    /// Get the principal each lender contributed to a loan funded from a request
+    /// (empty for loans with a single lender or funded by the protocol)
+    pub fn get_loan_shares(env: Env, loan_id: u32) -> Map<Address, i128> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("SHARES"), loan_id))
+            .unwrap_or(Map::new(&env))
+    }
+
+    /// Set the annual rate of credit lines opened from now on (owner or risk manager)
+    /// Borrowers within a rate tier pay the tier's line rate instead.
+    pub fn set_line_rate(env: Env, caller: Address, rate_bps: i128) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+
+        if !(0..=10000).contains(&rate_bps) {
+            panic!("Interest rate must be between 0 and 10000 basis points");
+        }
+
+        env.storage().instance().set(&LINE_RATE, &rate_bps);
+    }
+
+    /// Get the annual rate of new credit lines, in basis points (default 1200)
+    pub fn get_line_rate(env: Env) -> i128 {
+        env.storage().instance().get(&LINE_RATE).unwrap_or(1200)
+    }
+
+    /// Open a revolving credit line with the borrower's available limit as its limit
+    /// That is their current `max_loan` less what they have reserved as a guarantor.
+    /// Draws are priced at the borrower's line rate (`get_line_rate_for`) when the line is
+    /// opened.
+    pub fn open_credit_line(env: Env, borrower: Address) -> CreditLine {
+        borrower.require_auth();
+        pause::require_not_paused(&env, PauseFlag::NewLoans);
+
+        if let Some(line) = Self::load_credit_line(&env, &borrower) {
+            if line.status != CreditLineStatus::Closed {
+                panic!("Credit line already exists");
+            }
+        }
+        if Self::is_blacklisted(&env, &borrower) {
+            panic!("User is blacklisted");
+        }
+        if Self::get_max_loan_amount(env.clone(), borrower.clone()) == 0 {
+            panic!("User has no credit score. Please initialize your credit score first.");
+        }
+        let limit = Self::available_limit(&env, &borrower);
+        if limit == 0 {
+            panic!("No credit limit available");
+        }
+
+        let now = env.ledger().timestamp();
+        let line = CreditLine {
+            borrower: borrower.clone(),
+            limit,
+            rate: Self::get_line_rate_for(env.clone(), borrower.clone()),
+            drawn: 0,
+            interest: 0,
+            accrued_at: now,
+            next_due: now + PAYMENT_PERIOD as u64,
+            min_due: 0,
+            pool: Self::get_lending_pool(env.clone()),
+            status: CreditLineStatus::Open,
+        };
+        Self::save_credit_line(&env, &line);
+
+        let mut stats = Self::get_stats(env.clone());
+        stats.active_count += 1;
+        env.storage().instance().set(&STATS, &stats);
+
+        line
+    }
+
+    /// Draw `amount` from an open credit line, up to its limit
+    /// The balance also stays within the borrower's available limit, which shrinks with the
+    /// loans they guarantee. Not allowed while a minimum payment is overdue.
+    pub fn draw(env: Env, borrower: Address, amount: i128) {
+        borrower.require_auth();
+        pause::require_not_paused(&env, PauseFlag::NewLoans);
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        let mut line = Self::get_credit_line(env.clone(), borrower.clone());
+        if line.status != CreditLineStatus::Open {
+            panic!("Credit line is not open");
+        }
+        if line.min_due > 0 && env.ledger().timestamp() > line.next_due {
+            panic!("Minimum payment overdue");
+        }
+        if Self::is_blacklisted(&env, &borrower) {
+            panic!("User is blacklisted");
+        }
+        if line.drawn + amount > line.limit.min(Self::available_limit(&env, &borrower)) {
+            panic!("Amount exceeds credit line limit");
+        }
+
+        match &line.pool {
+            Some(pool) => LendingPoolMiniClient::new(&env, pool).disburse_loan(&borrower, &amount),
+            None => Self::mint_loan_tokens(&env, borrower.clone(), amount),
+        }
+        line.drawn += amount;
+        Self::save_credit_line(&env, &line);
+
+        let mut stats = Self::get_stats(env.clone());
+        stats.total_disbursed += amount;
+        stats.outstanding_principal += amount;
+        env.storage().instance().set(&STATS, &stats);
+    }
+
+    /// Repay up to `amount` of a credit line, accrued interest first
+    /// Counts toward the minimum payment due. Returns the amount repaid.
+    pub fn repay(env: Env, borrower: Address, amount: i128) -> i128 {
+        borrower.require_auth();
+        pause::require_not_paused(&env, PauseFlag::Repayments);
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        let mut line = Self::get_credit_line(env.clone(), borrower.clone());
+        if line.status != CreditLineStatus::Open {
+            panic!("Credit line is not open");
+        }
+
+        let paid = amount.min(line.drawn + line.interest);
+        if paid == 0 {
+            panic!("Nothing to repay");
+        }
+        let interest_part = paid.min(line.interest);
+        let principal_part = paid - interest_part;
+        Self::pay_line_funder(&env, &line, &borrower, paid, principal_part);
+
+        line.interest -= interest_part;
+        line.drawn -= principal_part;
+        line.min_due = (line.min_due - paid).max(0);
+        Self::save_credit_line(&env, &line);
+
+        let mut stats = Self::get_stats(env.clone());
+        stats.total_repaid += paid;
+        stats.outstanding_principal -= principal_part;
+        stats.interest_earned += interest_part;
+        env.storage().instance().set(&STATS, &stats);
+
+        paid
+    }
+
+    /// Close a fully repaid credit line
+    pub fn close_credit_line(env: Env, borrower: Address) {
+        borrower.require_auth();
+
+        let mut line = Self::get_credit_line(env.clone(), borrower);
+        if line.status != CreditLineStatus::Open {
+            panic!("Credit line is not open");
+        }
+        if line.drawn + line.interest > 0 {
+            panic!("Credit line has an outstanding balance");
+        }
+
+        line.status = CreditLineStatus::Closed;
+        Self::save_credit_line(&env, &line);
+
+        let mut stats = Self::get_stats(env.clone());
+        stats.active_count -= 1;
+        env.storage().instance().set(&STATS, &stats);
+    }
+
+    /// Mark a credit line as defaulted once a minimum payment is overdue past the grace
+    /// period
+    /// Anyone can call this. The borrower's score is penalized like a defaulted loan.
+    pub fn mark_line_defaulted(env: Env, borrower: Address) {
+        let mut line = Self::get_credit_line(env.clone(), borrower.clone());
+
+        if line.status != CreditLineStatus::Open {
+            panic!("Credit line is not open");
+        }
+        let deadline = line.next_due + GRACE_PERIOD as u64;
+        if line.min_due == 0 || env.ledger().timestamp() <= deadline {
+            panic!("Minimum payment is not past due");
+        }
+
+        line.status = CreditLineStatus::Defaulted;
+        Self::save_credit_line(&env, &line);
+
+        let mut stats = Self::get_stats(env.clone());
+        stats.outstanding_principal -= line.drawn;
+        stats.active_count -= 1;
+        stats.defaulted_count += 1;
+        env.storage().instance().set(&STATS, &stats);
+
+        Self::penalize_borrower(env.clone(), borrower, DEFAULT_PENALTY);
+    }
+
+    /// Write off a defaulted credit line (owner or admin)
+    /// For a pool-funded line, like `write_off`, the reserve covers the drawn principal as
+    /// far as it goes and the pool absorbs the rest. A line funded by minting is a loss for
+    /// the protocol alone and leaves the reserve untouched. Returns the amount covered by
+    /// the reserve.
+    pub fn write_off_line(env: Env, caller: Address, borrower: Address) -> i128 {
+        access_control::require_role(&env, Role::Admin, &caller);
+
+        let mut line = Self::get_credit_line(env.clone(), borrower);
+        if line.status != CreditLineStatus::Defaulted {
+            panic!("Credit line is not defaulted");
+        }
+
+        let mut covered = 0;
+        if let Some(pool) = &line.pool {
+            covered = line.drawn.min(Self::reserve_balance(env.clone()));
+            Self::set_reserve(&env, Self::reserve_balance(env.clone()) - covered);
+            if covered > 0 {
+                let contract = env.current_contract_address();
+                Self::pay_line_funder(&env, &line, &contract, covered, covered);
+            }
+            LendingPoolMiniClient::new(&env, pool).register_loss(&(line.drawn - covered));
+        }
+
+        line.status = CreditLineStatus::WrittenOff;
+        Self::save_credit_line(&env, &line);
+        covered
+    }
+
+    /// Get a borrower's credit line with interest accrued and statements issued up to now
+    pub fn get_credit_line(env: Env, borrower: Address) -> CreditLine {
+        let mut line = Self::load_credit_line(&env, &borrower).expect("Credit line not found");
+        if line.status == CreditLineStatus::Open {
+            Self::accrue_line(&env, &mut line);
+        }
+        line
+    }
+
+    fn load_credit_line(env: &Env, borrower: &Address) -> Option<CreditLine> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("LINE"), borrower.clone()))
+    }
+
+    fn save_credit_line(env: &Env, line: &CreditLine) {
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("LINE"), line.borrower.clone()), line);
+    }
+
+    /// Accrue interest on the drawn principal and issue the statement of each payment
+    /// period that ended with its minimum paid
+    /// The timestamp only moves once some interest accrued, so frequent calls cannot round
+    /// it away.
+    fn accrue_line(env: &Env, line: &mut CreditLine) {
+        let now = env.ledger().timestamp();
+        let elapsed = (now - line.accrued_at) as i128;
+        let interest = line.drawn * line.rate * elapsed / (10000 * YEAR);
+        if interest > 0 || line.drawn == 0 {
+            line.interest += interest;
+            line.accrued_at = now;
+        }
+
+        if line.min_due > 0 || now < line.next_due {
+            return;
+        }
+        let balance = line.drawn + line.interest;
+        if balance == 0 {
+            // Nothing owed: skip ahead to the period containing now
+            let periods = (now - line.next_due) / PAYMENT_PERIOD as u64 + 1;
+            line.next_due += periods * PAYMENT_PERIOD as u64;
+        } else {
+            line.next_due += PAYMENT_PERIOD as u64;
+            line.min_due = (balance * MIN_PAYMENT_BPS / 10000)
+                .max(line.interest)
+                .min(balance);
+        }
+    }
+
+    /// Pay `amount` from `from` to whoever funds a credit line's draws, after the reserve
+    /// and protocol fee cuts of its interest
+    fn pay_line_funder(
+        env: &Env,
+        line: &CreditLine,
+        from: &Address,
+        amount: i128,
+        principal: i128,
+    ) {
+        let amount = amount - Self::keep_protocol_cut(env, from, amount - principal);
+        let token_client = soroban_sdk::token::Client::new(env, &Self::get_token_address(env));
+        match &line.pool {
+            Some(pool) => {
+                token_client.transfer(from, pool, &amount);
+                let interest = amount - principal;
+                LendingPoolMiniClient::new(env, pool).register_repayment(&principal, &interest);
+            }
+            None => {
+                if *from != env.current_contract_address() {
+                    token_client.transfer(from, env.current_contract_address(), &amount);
+                }
+            }
+        }
+    }
+
+    /// Pay `amount` from `from` to the lenders of a loan
+    /// The reserve cut and protocol fee share of the interest are kept by this contract
+    /// first. Pool-funded loans report the principal and interest to the pool. Loans funded
+    /// from a request split it pro-rata to each lender's principal, with the rounding
+    /// remainder going to the last lender.
+    fn pay_lenders(env: &Env, loan_id: u32, from: &Address, amount: i128, principal: i128) {
+        let token_address = Self::get_token_address(env);
+        let token_client = soroban_sdk::token::Client::new(env, &token_address);
+
+        let amount = amount - Self::keep_protocol_cut(env, from, amount - principal);
+
+        if let Some(pool) = Self::get_loan_pool(env, loan_id) {
+            token_client.transfer(from, &pool, &amount);
+            let interest = amount - principal;
+            LendingPoolMiniClient::new(env, &pool).register_repayment(&principal, &interest);
+            return;
+        }
+
+        let shares = Self::get_loan_shares(env.clone(), loan_id);
+        if shares.is_empty() {
+            token_client.transfer(from, Self::repayment_recipient(env, loan_id), &amount);
+            return;
+        }
+
+        Self::pay_shares(&token_client, from, &shares, amount);
+    }
+
+    /// Split `amount` of a token between request funders in proportion to their shares
+    /// The last funder receives the rounding remainder.
+    fn pay_shares(
+        token_client: &soroban_sdk::token::Client,
+        from: &Address,
+        shares: &Map<Address, i128>,
+        amount: i128,
+    ) {
+        let funded: i128 = shares.values().iter().sum();
+        let last = shares.len() - 1;
+        let mut paid = 0;
+        for (i, (lender, share)) in shares.iter().enumerate() {
+            let part = if i as u32 == last {
+                amount - paid
+            } else {
+                amount * share / funded
+            };
+            if part > 0 {
+                token_client.transfer(from, &lender, &part);
+            }
+            paid += part;
+        }
+    }
+
+    /// Keep the reserve cut and protocol fee share of `interest` paid by `from` on this
+    /// contract. Returns the amount kept.
+    fn keep_protocol_cut(env: &Env, from: &Address, interest: i128) -> i128 {
+        let reserve_cut = interest * Self::get_reserve_rate(env.clone()) / 10000;
+        let fee_cut = interest * Self::get_fee_config(env.clone()).protocol_fee_bps / 10000;
+        let kept = reserve_cut + fee_cut;
+        if kept > 0 && *from != env.current_contract_address() {
+            let token_client = soroban_sdk::token::Client::new(env, &Self::get_token_address(env));
+            token_client.transfer(from, env.current_contract_address(), &kept);
+        }
+        Self::set_reserve(env, Self::reserve_balance(env.clone()) + reserve_cut);
+        Self::add_fees(env, fee_cut);
+        kept
+    }
+
+    /// Set the lending pool that funds new protocol loans (owner or risk manager)
+    /// Loans opened before keep being repaid to where they were funded from.
+    pub fn set_lending_pool(env: Env, caller: Address, lending_pool: Address) {
+        access_control::require_role(&env, Role::RiskManager, &caller);
+        env.storage().instance().set(&LENDING_POOL, &lending_pool);
+    }
+
+    /// Get the lending pool that funds new protocol loans (None while loans are minted)
+    pub fn get_lending_pool(env: Env) -> Option<Address> {
+        env.storage().instance().get(&LENDING_POOL)
+    }
+
+    /// Pool that funded a loan, if any
+    fn get_loan_pool(env: &Env, loan_id: u32) -> Option<Address> {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("POOLED"), loan_id))
+    }
+
+    /// Pay `amount` held by this contract to the lenders of a loan
+    /// Protocol-funded loans are repaid to this contract itself, so only the reserve and
+    /// fee cuts of the interest are accounted for.
+    fn pay_lenders_from_contract(env: &Env, loan_id: u32, amount: i128, principal: i128) {
+        let contract = env.current_contract_address();
+        if Self::is_protocol_funded(env, loan_id) {
+            Self::keep_protocol_cut(env, &contract, amount - principal);
+        } else {
+            Self::pay_lenders(env, loan_id, &contract, amount, principal);
+        }
+    }
+
+    /// Whether a loan was funded by this contract itself (minted, not a pool or P2P lenders)
+    fn is_protocol_funded(env: &Env, loan_id: u32) -> bool {
+        Self::get_loan_pool(env, loan_id).is_none()
+            && Self::get_loan_lender(env.clone(), loan_id).is_none()
+            && Self::get_loan_shares(env.clone(), loan_id).is_empty()
+    }
+
+    /// Address repayments of a loan are paid to: its lender, or this contract
+    fn repayment_recipient(env: &Env, loan_id: u32) -> Address {
+        Self::get_loan_lender(env.clone(), loan_id).unwrap_or(env.current_contract_address())
+    }
+
+    /// Get loan information
+    /// Loans still in the legacy layout are converted on read
+    pub fn get_loan(env: Env, loan_id: u32) -> Loan {
+        let loan_key = (symbol_short!("LOAN"), loan_id);
+        let raw: Val = env
+            .storage()
+            .persistent()
+            .get(&loan_key)
+            .expect("Loan not found");
+
+        if let Ok(loan) = Loan::try_from_val(&env, &raw) {
+            return loan;
+        }
+
+        LegacyLoan::try_from_val(&env, &raw)
+            .expect("Loan record has an unknown layout")
+            .into()
+    }
+
+    /// Rewrite a legacy loan record in the current layout
+    /// Anyone can call this: the stored data is only re-encoded, never changed
+    pub fn migrate_loan(env: Env, loan_id: u32) {
+        let loan = Self::get_loan(env.clone(), loan_id);
+        let loan_key = (symbol_short!("LOAN"), loan_id);
+        env.storage()
+            .persistent()
+            .set(&loan_key, &loan);
+    }
+
+    /// Get loan ID for a borrower
+    pub fn get_loan_id(env: Env, borrower: Address) -> Option<u32> {
+        let borrower_key = (symbol_short!("BORROWER"), borrower);
+        env.storage()
+            .persistent()
+            .get(&borrower_key)
+            .unwrap_or(None)
+    }
+
+    /// Get portfolio-wide loan statistics
+    pub fn get_stats(env: Env) -> PortfolioStats {
+        env.storage()
+            .instance()
+            .get(&STATS)
+            .unwrap_or_default()
+    }
+
+    /// Get total number of loans ever created
+    /// Loan ids are sequential, so valid ids are 0..get_total_loans()
+    pub fn get_total_loans(env: Env) -> u32 {
+        Self::get_next_loan_id(env)
+    }
+
+    /// List loans by id
+    /// Scans at most `limit` ids (capped at MAX_PAGE_SIZE) starting at `start` and returns
+    /// those matching `status_filter`. A filtered page can hold fewer than `limit` loans;
+    /// continue from `start + limit` until it reaches get_total_loans().
+    pub fn list_loans(
+        env: Env,
+        start: u32,
+        limit: u32,
+        status_filter: Option<LoanStatus>,
+    ) -> Vec<(u32, Loan)> {
+        let total = Self::get_next_loan_id(env.clone());
+        let end = start.saturating_add(limit.min(MAX_PAGE_SIZE)).min(total);
+
+        let mut page = Vec::new(&env);
+        for loan_id in start..end {
+            let loan = Self::get_loan(env.clone(), loan_id);
+            if status_filter.is_none_or(|status| loan.status == status) {
+                page.push_back((loan_id, loan));
+            }
+        }
+        page
+    }
+
+    /// Get next loan ID
+    fn get_next_loan_id(env: Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&symbol_short!("LOAN_ID"))
+            .unwrap_or(0)
+    }
+
+    /// Increment loan ID
+    fn increment_loan_id(env: Env) {
+        let current: u32 = Self::get_next_loan_id(env.clone());
+        env.storage().instance().set(&symbol_short!("LOAN_ID"), &(current + 1));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::credit_scoring::{CreditScoringMini, CreditScoringMiniClient};
+    use crate::identity_registry::{IdentityRegistry, IdentityRegistryClient};
+    use crate::lending_pool::{LendingPoolMini, Tranche};
+    use crate::mock_oracle::{MockOracle, MockOracleClient};
+    use crate::mock_usdc::{MockUSDC, MockUSDCClient};
+    use crate::tranche_token::{TrancheShareToken, TrancheShareTokenClient};
+    use soroban_sdk::{
+        testutils::{Address as _, Ledger, MockAuth, MockAuthInvoke},
+        String,
+    };
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_loan_manager_init() {
+        let env = Env::default();
+        let contract_id = env.register_contract(None, LoanManagerMicro);
+        let client = LoanManagerMicroClient::new(&env, &contract_id);
+
+        // Create addresses
+        let owner = Address::generate(&env);
+        let credit_scoring = Address::generate(&env);
+        let token = Address::generate(&env);
+        
+        // Initialize (lending_pool was removed from the system)
+        client.initialize(&owner, &credit_scoring, &token, &500);
+        assert_eq!(client.get_owner(), owner);
+
+        // Re-initializing must not replace the owner
+        let attacker = Address::generate(&env);
+        assert!(client
+            .try_initialize(&attacker, &credit_scoring, &token, &500)
+            .is_err());
+    }
+
+    struct Setup {
+        env: Env,
+        admin: Address,
+        client: LoanManagerMicroClient<'static>,
+        credit: CreditScoringMiniClient<'static>,
+        token: MockUSDCClient<'static>,
+    }
+
+    /// Register token, credit scoring and loan manager wired together
+    fn setup() -> Setup {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        let admin = Address::generate(&env);
+
+        let token_id = env.register(MockUSDC, ());
+        let token = MockUSDCClient::new(&env, &token_id);
+        token.initialize(
+            &admin,
+            &String::from_str(&env, "Mock USDC"),
+            &String::from_str(&env, "USDC"),
+            &6u32,
+        );
+
+        let contract_id = env.register(LoanManagerMicro, ());
+        let client = LoanManagerMicroClient::new(&env, &contract_id);
+        token.set_loan_manager(&contract_id);
+
+        // The loan manager is a risk manager so it can reward borrowers
+        let credit_id = env.register(CreditScoringMini, ());
+        let credit = CreditScoringMiniClient::new(&env, &credit_id);
+        credit.initialize(&admin);
+        credit.grant_role(&admin, &Role::RiskManager, &contract_id);
+
+        client.initialize(&admin, &credit_id, &token_id, &500);
+
+        Setup { env, admin, client, credit, token }
+    }
+
+    /// Create a borrower with an initialized credit score
+    fn new_borrower(s: &Setup) -> Address {
+        let borrower = Address::generate(&s.env);
+        s.credit.initialize_user(&borrower);
+        borrower
+    }
+
+    #[test]
+    fn test_setters_need_their_role() {
+        let s = setup();
+        let fees = FeeConfig {
+            origination_fee_bps: 100,
+            protocol_fee_bps: 500,
+        };
+        let pool = Address::generate(&s.env);
+        let merchant = Address::generate(&s.env);
+        let name = String::from_str(&s.env, "Shop");
+
+        // Risk managers wire the protocol together but cannot touch fees
+        let risk_manager = Address::generate(&s.env);
+        assert!(s.client.try_set_lending_pool(&risk_manager, &pool).is_err());
+        s.client.grant_role(&s.admin, &Role::RiskManager, &risk_manager);
+        s.client.set_lending_pool(&risk_manager, &pool);
+        s.client.set_identity_registry(&risk_manager, &pool);
+        s.client.register_merchant(&risk_manager, &merchant, &name, &200);
+        assert_eq!(s.client.get_lending_pool(), Some(pool.clone()));
+        assert!(s.client.try_set_fee_config(&risk_manager, &fees).is_err());
+
+        // Treasurers set fees but cannot choose where they are withdrawn to
+        let treasurer = Address::generate(&s.env);
+        s.client.grant_role(&s.admin, &Role::Treasurer, &treasurer);
+        s.client.set_fee_config(&treasurer, &fees);
+        assert_eq!(s.client.get_fee_config(), fees);
+        assert!(s.client.try_set_treasury(&treasurer, &treasurer).is_err());
+        assert!(s.client.try_set_lending_pool(&treasurer, &pool).is_err());
+
+        // Revoking a role takes the setters away again
+        s.client.revoke_role(&s.admin, &Role::RiskManager, &risk_manager);
+        assert!(!s.client.has_role(&Role::RiskManager, &risk_manager));
+        assert!(s.client.try_remove_merchant(&risk_manager, &merchant).is_err());
+
+        // Ownership moves only once the new owner accepts, then the old one is out
+        let new_owner = Address::generate(&s.env);
+        s.client.transfer_ownership(&new_owner);
+        assert_eq!(s.client.get_pending_owner(), Some(new_owner.clone()));
+        s.client.set_treasury(&s.admin, &pool);
+        s.client.accept_ownership();
+        assert_eq!(s.client.get_owner(), new_owner);
+        assert!(s.client.try_set_treasury(&s.admin, &pool).is_err());
+        s.client.set_treasury(&new_owner, &pool);
+        s.client.remove_merchant(&new_owner, &merchant);
+        assert_eq!(s.client.get_merchant(&merchant), None);
+    }
+
+    #[test]
+    fn test_request_and_repay_loan() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+
+        let loan_id = s.client.request_loan(&borrower, &100_000_000, &2, &None);
+        assert_eq!(s.token.balance(&borrower), 100_000_000);
+
+        let loan = s.client.get_loan(&loan_id);
+        assert_eq!(loan.total_amount, 105_000_000);
+        assert_eq!(loan.status, LoanStatus::Active);
+
+        s.token.mint(&borrower, &5_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+        s.client.pay_installment(&borrower, &loan_id);
+
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Repaid);
+        assert_eq!(s.credit.get_score_value(&borrower), 310);
+    }
+
+    #[test]
+    fn test_pause_new_loans_keeps_repayments_open() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.request_loan(&borrower, &10_000_000, &2, &None);
+
+        s.client.pause(&s.admin, &PauseFlag::NewLoans);
+        assert!(s.client.is_paused(&PauseFlag::NewLoans));
+
+        let other = new_borrower(&s);
+        assert!(s.client.try_request_loan(&other, &10_000_000, &1, &None).is_err());
+
+        // Borrowing is halted but the existing loan can still be repaid
+        s.client.pay_installment(&borrower, &loan_id);
+
+        s.client.unpause(&s.admin, &PauseFlag::NewLoans);
+        s.client.request_loan(&other, &10_000_000, &1, &None);
+    }
+
+    #[test]
+    fn test_pause_new_loans_gates_every_origination() {
+        let s = setup();
+        let collateral = enable_secured_loans(&s, 5_000);
+        let registry = enable_identity_checks(&s);
+        let borrowers: [Address; 8] = core::array::from_fn(|_| new_borrower(&s));
+        let mut members = Vec::new(&s.env);
+        for _ in 0..3 {
+            let member = new_borrower(&s);
+            verify_identity(&s, &registry, &member, VerificationLevel::Level1);
+            members.push_back(member);
+        }
+        collateral.mint(&borrowers[1], &100_000_000);
+
+        let lender = Address::generate(&s.env);
+        s.token.mint(&lender, &300_000_000);
+        let min_level = VerificationLevel::Unverified;
+        let offer_id = s.client.create_offer(&lender, &10_000_000, &500, &1, &0, &min_level);
+        let purpose = String::from_str(&s.env, "bafy");
+        let request_id = s
+            .client
+            .post_request(&borrowers[2], &10_000_000, &500, &1, &purpose, &1_000);
+        let merchant = Address::generate(&s.env);
+        let name = String::from_str(&s.env, "Tienda");
+        s.client.register_merchant(&s.admin, &merchant, &name, &300);
+        s.client.open_credit_line(&borrowers[3]);
+
+        s.client.pause(&s.admin, &PauseFlag::NewLoans);
+        let b = &borrowers;
+        assert!(s.client.try_request_loan(&b[0], &10_000_000, &1, &None).is_err());
+        assert!(s
+            .client
+            .try_request_secured_loan(&b[1], &10_000_000, &1, &100_000_000)
+            .is_err());
+        assert!(s
+            .client
+            .try_create_offer(&lender, &10_000_000, &500, &1, &0, &min_level)
+            .is_err());
+        assert!(s.client.try_accept_offer(&b[4], &offer_id).is_err());
+        assert!(s
+            .client
+            .try_post_request(&b[5], &10_000_000, &500, &1, &purpose, &1_000)
+            .is_err());
+        assert!(s.client.try_fund_request(&lender, &request_id, &10_000_000).is_err());
+        assert!(s.client.try_form_circle(&members, &5000).is_err());
+        assert!(s.client.try_bnpl_purchase(&b[6], &merchant, &10_000_000, &2).is_err());
+        assert!(s.client.try_open_credit_line(&b[7]).is_err());
+        assert!(s.client.try_draw(&b[3], &10_000_000).is_err());
+
+        // Every one of them goes through once borrowing reopens
+        s.client.unpause(&s.admin, &PauseFlag::NewLoans);
+        s.client.request_loan(&b[0], &10_000_000, &1, &None);
+        s.client.request_secured_loan(&b[1], &10_000_000, &1, &100_000_000);
+        s.client.create_offer(&lender, &10_000_000, &500, &1, &0, &min_level);
+        s.client.accept_offer(&b[4], &offer_id);
+        s.client.post_request(&b[5], &10_000_000, &500, &1, &purpose, &1_000);
+        s.client.fund_request(&lender, &request_id, &10_000_000);
+        let circle_id = s.client.form_circle(&members, &5000);
+        s.client.bnpl_purchase(&b[6], &merchant, &10_000_000, &2);
+        s.client.open_credit_line(&b[7]);
+        s.client.draw(&b[3], &10_000_000);
+
+        s.client.pause(&s.admin, &PauseFlag::NewLoans);
+        let member = members.get(0).unwrap();
+        assert!(s.client.try_request_circle_loan(&member, &10_000_000, &1).is_err());
+        s.client.unpause(&s.admin, &PauseFlag::NewLoans);
+        s.client.request_circle_loan(&member, &10_000_000, &1);
+        assert_eq!(s.client.get_member_circle(&member), Some(circle_id));
+    }
+
+    #[test]
+    fn test_pause_repayments() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.request_loan(&borrower, &10_000_000, &2, &None);
+
+        let pauser = Address::generate(&s.env);
+        s.client.grant_role(&s.admin, &Role::Pauser, &pauser);
+        s.client.open_credit_line(&borrower);
+        s.client.draw(&borrower, &10_000_000);
+        s.client.pause(&pauser, &PauseFlag::Repayments);
+        assert!(s.client.try_pay_installment(&borrower, &loan_id).is_err());
+        assert!(s.client.try_repay(&borrower, &1_000_000).is_err());
+
+        s.client.unpause(&pauser, &PauseFlag::Repayments);
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(s.client.get_loan(&loan_id).paid_payments, 1);
+        assert_eq!(s.client.repay(&borrower, &1_000_000), 1_000_000);
+    }
+
+    #[test]
+    fn test_frozen_borrower() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.request_loan(&borrower, &10_000_000, &2, &None);
+
+        // Freezing the borrower mid-loan blocks repayments
+        s.token.set_authorized(&borrower, &false);
+        assert!(s.client.try_pay_installment(&borrower, &loan_id).is_err());
+        assert_eq!(s.client.get_loan(&loan_id).paid_payments, 0);
+
+        // and new loans, since the disbursement cannot be minted to a frozen account
+        let other_loan = new_borrower(&s);
+        s.token.set_authorized(&other_loan, &false);
+        assert!(s.client.try_request_loan(&other_loan, &10_000_000, &1, &None).is_err());
+
+        // A clawback leaves the loan outstanding with less to repay it from
+        s.token.clawback(&borrower, &2_000_000);
+        s.token.set_authorized(&borrower, &true);
+        s.client.pay_installment(&borrower, &loan_id);
+        assert!(s.client.try_pay_installment(&borrower, &loan_id).is_err());
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Active);
+    }
+
+    #[test]
+    fn test_portfolio_stats() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+
+        let loan_id = s.client.request_loan(&borrower, &100_000_000, &2, &None);
+        let stats = s.client.get_stats();
+        assert_eq!(stats.total_disbursed, 100_000_000);
+        assert_eq!(stats.outstanding_principal, 100_000_000);
+        assert_eq!(stats.active_count, 1);
+
+        s.token.mint(&borrower, &5_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+        let stats = s.client.get_stats();
+        assert_eq!(stats.total_repaid, 52_500_000);
+        assert_eq!(stats.outstanding_principal, 50_000_000);
+        assert_eq!(stats.interest_earned, 2_500_000);
+
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(
+            s.client.get_stats(),
+            PortfolioStats {
+                total_disbursed: 100_000_000,
+                total_repaid: 105_000_000,
+                outstanding_principal: 0,
+                interest_earned: 5_000_000,
+                active_count: 0,
+                repaid_count: 1,
+                defaulted_count: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_list_loans() {
+        let s = setup();
+        let first = new_borrower(&s);
+        let second = new_borrower(&s);
+
+        let repaid_id = s.client.request_loan(&first, &10_000_000, &1, &None);
+        s.token.mint(&first, &500_000);
+        s.client.pay_installment(&first, &repaid_id);
+        let active_id = s.client.request_loan(&second, &10_000_000, &1, &None);
+
+        assert_eq!(s.client.get_total_loans(), 2);
+        assert_eq!(s.client.list_loans(&0, &10, &None).len(), 2);
+
+        let active = s.client.list_loans(&0, &10, &Some(LoanStatus::Active));
+        assert_eq!(active.len(), 1);
+        assert_eq!(active.get(0).unwrap().0, active_id);
+
+        let repaid = s.client.list_loans(&0, &1, &Some(LoanStatus::Repaid));
+        assert_eq!(repaid.len(), 1);
+        assert_eq!(repaid.get(0).unwrap().0, repaid_id);
+
+        assert_eq!(s.client.list_loans(&1, &10, &Some(LoanStatus::Repaid)).len(), 0);
+    }
+
+    #[test]
+    fn test_legacy_loan_migration() {
+        let env = Env::default();
+        let contract_id = env.register(LoanManagerMicro, ());
+        let client = LoanManagerMicroClient::new(&env, &contract_id);
+
+        // Write a loan the way the contract stored it before the enum change
+        let borrower = Address::generate(&env);
+        let legacy = LegacyLoan {
+            borrower: borrower.clone(),
+            amount: 1_000,
+            total_amount: 1_050,
+            interest_rate: 500,
+            num_payments: 2,
+            paid_payments: 2,
+            payment_amount: 525,
+            created_at: 0,
+            status: 1,
+        };
+        env.as_contract(&contract_id, || {
+            env.storage()
+                .persistent()
+                .set(&(symbol_short!("LOAN"), 0u32), &legacy);
+        });
+
+        // Legacy loans are readable before migration
+        assert_eq!(client.get_loan(&0).status, LoanStatus::Repaid);
+
+        client.migrate_loan(&0);
+
+        let stored: Loan = env.as_contract(&contract_id, || {
+            env.storage()
+                .persistent()
+                .get(&(symbol_short!("LOAN"), 0u32))
+                .unwrap()
+        });
+        assert_eq!(stored.status, LoanStatus::Repaid);
+        assert_eq!(stored.borrower, borrower);
+        assert_eq!(stored.total_amount, 1_050);
+
+        // Legacy statuses outside 0-2 were never written and are rejected
+        let corrupt = LegacyLoan { status: 3, ..legacy };
+        env.as_contract(&contract_id, || {
+            env.storage()
+                .persistent()
+                .set(&(symbol_short!("LOAN"), 1u32), &corrupt);
+        });
+        assert!(client.try_get_loan(&1).is_err());
+    }
+
+    /// Register a second token used as collateral and enable secured loans
+    fn enable_secured_loans(s: &Setup, ltv_bps: i128) -> MockUSDCClient<'static> {
+        let collateral_id = s.env.register(MockUSDC, ());
+        let collateral = MockUSDCClient::new(&s.env, &collateral_id);
+        collateral.initialize(
+            &s.admin,
+            &String::from_str(&s.env, "Collateral"),
+            &String::from_str(&s.env, "COL"),
+            &6u32,
+        );
+
+        let config = CollateralConfig {
+            token: collateral_id,
+            ltv_bps,
+        };
+        s.client.set_collateral_config(&s.admin, &config);
+        collateral
+    }
+
+    #[test]
+    fn test_secured_loan_raises_limit_and_returns_collateral() {
+        let s = setup();
+        let collateral = enable_secured_loans(&s, 5_000);
+        let borrower = new_borrower(&s);
+        collateral.mint(&borrower, &1_000_000_000);
+
+        // 1,400 USDC is above the ~1,000 USDC unsecured limit of a new borrower
+        assert!(s.client.try_request_loan(&borrower, &1_400_000_000, &1, &None).is_err());
+
+        // 1,000 COL at 50% LTV adds 500 USDC of headroom
+        assert!(s
+            .client
+            .try_request_secured_loan(&borrower, &1_600_000_000, &1, &1_000_000_000)
+            .is_err());
+        let loan_id = s
+            .client
+            .request_secured_loan(&borrower, &1_400_000_000, &1, &1_000_000_000);
+
+        assert_eq!(collateral.balance(&borrower), 0);
+        assert_eq!(collateral.balance(&s.client.address), 1_000_000_000);
+        assert_eq!(s.client.get_escrowed(&collateral.address), 1_000_000_000);
+        let position = s.client.get_collateral(&loan_id).unwrap();
+        assert_eq!(position.amount, 1_000_000_000);
+
+        // Full repayment hands the collateral back
+        s.token.mint(&borrower, &70_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Repaid);
+        assert_eq!(collateral.balance(&borrower), 1_000_000_000);
+        assert_eq!(s.client.get_escrowed(&collateral.address), 0);
+        assert_eq!(s.client.get_collateral(&loan_id), None);
+    }
+
+    #[test]
+    fn test_secured_loans_need_config() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        assert!(s
+            .client
+            .try_request_secured_loan(&borrower, &10_000_000, &1, &10_000_000)
+            .is_err());
+
+        // LTV above 100% is rejected
+        let config = CollateralConfig {
+            token: s.token.address.clone(),
+            ltv_bps: 10_001,
+        };
+        assert!(s.client.try_set_collateral_config(&s.admin, &config).is_err());
+    }
+
+    #[test]
+    fn test_default_forfeits_collateral() {
+        let s = setup();
+        let collateral = enable_secured_loans(&s, 5_000);
+        let borrower = new_borrower(&s);
+        collateral.mint(&borrower, &100_000_000);
+
+        let loan_id = s
+            .client
+            .request_secured_loan(&borrower, &100_000_000, &2, &100_000_000);
+        let due_date = s.client.get_next_due_date(&loan_id);
+        assert_eq!(due_date, PAYMENT_PERIOD);
+
+        // Late but still inside the grace period
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = (due_date + GRACE_PERIOD) as u64);
+        assert!(s.client.try_mark_defaulted(&loan_id).is_err());
+
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = (due_date + GRACE_PERIOD + 1) as u64);
+        s.client.mark_defaulted(&loan_id);
+
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Defaulted);
+        assert!(s.client.try_pay_installment(&borrower, &loan_id).is_err());
+
+        // The protocol minted the loan, so the collateral stays here and leaves escrow
+        assert_eq!(collateral.balance(&borrower), 0);
+        assert_eq!(collateral.balance(&s.client.address), 100_000_000);
+        assert_eq!(s.client.get_escrowed(&collateral.address), 0);
+        assert_eq!(s.client.get_collateral(&loan_id), None);
+        assert_eq!(s.client.get_forfeited(&loan_id).unwrap().amount, 100_000_000);
+
+        // and only the treasury can take it out
+        let treasury = Address::generate(&s.env);
+        s.client.set_treasury(&s.admin, &treasury);
+        assert!(s
+            .client
+            .try_withdraw_forfeited(&s.admin, &collateral.address, &100_000_001)
+            .is_err());
+        assert!(s
+            .client
+            .try_withdraw_forfeited(&s.admin, &s.token.address, &1)
+            .is_err());
+        s.client.withdraw_forfeited(&s.admin, &collateral.address, &100_000_000);
+        assert_eq!(collateral.balance(&treasury), 100_000_000);
+
+        let stats = s.client.get_stats();
+        assert_eq!(stats.active_count, 0);
+        assert_eq!(stats.defaulted_count, 1);
+        assert_eq!(stats.outstanding_principal, 0);
+        assert_eq!(s.credit.get_score_value(&borrower), 250);
+    }
+
+    #[test]
+    fn test_default_sends_collateral_to_the_pool() {
+        let s = setup();
+        let pool = enable_lending_pool(&s, 800_000_000, 200_000_000);
+        let collateral = enable_secured_loans(&s, 5_000);
+        let borrower = new_borrower(&s);
+        collateral.mint(&borrower, &100_000_000);
+
+        let loan_id = s
+            .client
+            .request_secured_loan(&borrower, &100_000_000, &2, &100_000_000);
+        assert_eq!(pool.get_lent_out(), 100_000_000);
+
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = (PAYMENT_PERIOD + GRACE_PERIOD + 1) as u64);
+        s.client.mark_defaulted(&loan_id);
+
+        // The pool funded the loan, so the pool receives the collateral
+        assert_eq!(collateral.balance(&pool.address), 100_000_000);
+        assert_eq!(collateral.balance(&s.client.address), 0);
+        assert_eq!(s.client.get_escrowed(&collateral.address), 0);
+
+        // Valued at par it covers the whole 100 USDC, so no tranche loses anything
+        s.client.write_off(&s.admin, &loan_id);
+        assert_eq!(pool.get_lent_out(), 0);
+        assert_eq!(pool.get_tranche(&Tranche::Junior).assets, 200_000_000);
+        let recovered = pool.get_recovered(&collateral.address);
+        assert_eq!(recovered.amount, 100_000_000);
+        assert_eq!(recovered.value, 100_000_000);
+
+        // Selling it below book value realizes the loss, junior first
+        let buyer = Address::generate(&s.env);
+        s.token.mint(&buyer, &80_000_000);
+        pool.sell_recovered(&s.admin, &buyer, &collateral.address, &100_000_000, &80_000_000);
+        assert_eq!(collateral.balance(&buyer), 100_000_000);
+        assert_eq!(pool.get_total_liquidity(), 980_000_000);
+        assert_eq!(pool.get_tranche(&Tranche::Junior).assets, 180_000_000);
+        assert_eq!(pool.get_tranche(&Tranche::Senior).assets, 800_000_000);
+        assert_eq!(pool.get_recovered(&collateral.address).amount, 0);
+    }
+
+    #[test]
+    fn test_write_off_nets_collateral_before_the_reserve() {
+        let s = setup();
+        let pool = enable_lending_pool(&s, 800_000_000, 200_000_000);
+        let collateral = enable_secured_loans(&s, 5_000);
+        s.client.set_reserve_rate(&s.admin, &10_000);
+
+        // 5 USDC of interest goes entirely to the reserve
+        let payer = new_borrower(&s);
+        let repaid = s.client.request_loan(&payer, &100_000_000, &1, &None);
+        s.token.mint(&payer, &5_000_000);
+        s.client.pay_installment(&payer, &repaid);
+        assert_eq!(s.client.reserve_balance(), 5_000_000);
+
+        // 40 USDC of collateral against a 100 USDC loan
+        let borrower = new_borrower(&s);
+        collateral.mint(&borrower, &40_000_000);
+        let loan_id = s
+            .client
+            .request_secured_loan(&borrower, &100_000_000, &1, &40_000_000);
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = (PAYMENT_PERIOD + GRACE_PERIOD + 1) as u64);
+        s.client.mark_defaulted(&loan_id);
+
+        // 40 recovered, 5 from the reserve, 55 lost by the junior tranche
+        let junior_before = pool.get_tranche(&Tranche::Junior).assets;
+        assert_eq!(s.client.write_off(&s.admin, &loan_id), 5_000_000);
+        assert_eq!(pool.get_tranche(&Tranche::Junior).assets, junior_before - 55_000_000);
+        assert_eq!(pool.get_recovered(&collateral.address).value, 40_000_000);
+        assert_eq!(pool.get_lent_out(), 0);
+    }
+
+    #[test]
+    fn test_collateral_valued_by_oracle() {
+        let s = setup();
+        let collateral = enable_secured_loans(&s, 5_000);
+
+        let oracle_id = s.env.register(MockOracle, ());
+        let oracle = MockOracleClient::new(&s.env, &oracle_id);
+        oracle.initialize(&s.admin, &7, &300);
+
+        s.env.ledger().with_mut(|li| li.timestamp = 10_000);
+        oracle.set_price(&Asset::Stellar(s.token.address.clone()), &10_000_000); // 1 USD
+        oracle.set_price(&Asset::Stellar(collateral.address.clone()), &20_000_000); // 2 USD
+
+        let config = OracleConfig {
+            oracle: oracle_id,
+            max_age: 3_600,
+        };
+        s.client.set_oracle_config(&s.admin, &config);
+
+        // 1,000 COL is worth 2,000 USDC; at 50% LTV it adds 1,000 USDC of headroom
+        let borrower = new_borrower(&s);
+        collateral.mint(&borrower, &1_000_000_000);
+        let loan_id = s
+            .client
+            .request_secured_loan(&borrower, &1_900_000_000, &1, &1_000_000_000);
+        assert_eq!(s.client.get_collateral_value(&loan_id), 2_000_000_000);
+
+        // Once the feed stops updating, collateral can no longer be valued
+        s.env.ledger().with_mut(|li| li.timestamp = 10_000 + 3_601);
+        assert!(s.client.try_get_collateral_value(&loan_id).is_err());
+
+        let other = new_borrower(&s);
+        collateral.mint(&other, &1_000_000_000);
+        assert!(s
+            .client
+            .try_request_secured_loan(&other, &100_000_000, &1, &1_000_000_000)
+            .is_err());
+    }
+
+    #[test]
+    fn test_liquidate_under_collateralized_loan() {
+        let s = setup();
+        let collateral = enable_secured_loans(&s, 5_000);
+
+        let oracle_id = s.env.register(MockOracle, ());
+        let oracle = MockOracleClient::new(&s.env, &oracle_id);
+        oracle.initialize(&s.admin, &7, &300);
+        oracle.set_price(&Asset::Stellar(s.token.address.clone()), &10_000_000);
+        oracle.set_price(&Asset::Stellar(collateral.address.clone()), &20_000_000);
+        let config = OracleConfig {
+            oracle: oracle_id,
+            max_age: 3_600,
+        };
+        s.client.set_oracle_config(&s.admin, &config);
+
+        let borrower = new_borrower(&s);
+        collateral.mint(&borrower, &1_000_000_000);
+        let loan_id = s
+            .client
+            .request_secured_loan(&borrower, &1_200_000_000, &4, &1_000_000_000);
+        assert_eq!(s.client.get_outstanding_debt(&loan_id), 1_260_000_000);
+
+        let liquidator = Address::generate(&s.env);
+        s.token.mint(&liquidator, &1_000_000_000);
+
+        // 2,000 USDC of collateral * 80% against 1,260 USDC of debt is healthy
+        assert!(s.client.get_health_factor(&loan_id) >= 10_000);
+        assert!(s
+            .client
+            .try_liquidate(&liquidator, &loan_id, &315_000_000)
+            .is_err());
+
+        // The collateral price drops to 1.5 USD: 1,500 * 80% / 1,260 < 1
+        oracle.set_price(&Asset::Stellar(collateral.address.clone()), &15_000_000);
+        assert!(s.client.get_health_factor(&loan_id) < 10_000);
+
+        // Liquidations are repayments, so they stop with the Repayments flag
+        s.client.pause(&s.admin, &PauseFlag::Repayments);
+        assert!(s
+            .client
+            .try_liquidate(&liquidator, &loan_id, &1_000_000_000)
+            .is_err());
+        s.client.unpause(&s.admin, &PauseFlag::Repayments);
+
+        // The close factor caps the repayment at 2 of the 4 installments
+        let seized = s.client.liquidate(&liquidator, &loan_id, &1_000_000_000);
+        assert_eq!(s.token.balance(&liquidator), 1_000_000_000 - 630_000_000);
+
+        // 630 USDC plus a 5% bonus, paid in collateral worth 1.5 USD
+        assert_eq!(seized, 441_000_000);
+        assert_eq!(collateral.balance(&liquidator), 441_000_000);
+        assert_eq!(s.client.get_collateral(&loan_id).unwrap().amount, 559_000_000);
+        assert_eq!(s.client.get_escrowed(&collateral.address), 559_000_000);
+
+        let loan = s.client.get_loan(&loan_id);
+        assert_eq!(loan.paid_payments, 2);
+        assert_eq!(loan.status, LoanStatus::Active);
+        assert_eq!(s.credit.get_score_value(&borrower), 275);
+
+        // The loan is healthy again, so a second liquidation is rejected
+        assert!(s.client.get_health_factor(&loan_id) >= 10_000);
+        assert!(s
+            .client
+            .try_liquidate(&liquidator, &loan_id, &315_000_000)
+            .is_err());
+
+        // Unsecured loans cannot be liquidated
+        let other = new_borrower(&s);
+        let unsecured = s.client.request_loan(&other, &10_000_000, &1, &None);
+        assert!(s
+            .client
+            .try_liquidate(&liquidator, &unsecured, &10_500_000)
+            .is_err());
+    }
+
+    #[test]
+    fn test_partial_liquidations_penalize_once() {
+        let s = setup();
+        let collateral = enable_secured_loans(&s, 5_000);
+        let oracle_id = s.env.register(MockOracle, ());
+        let oracle = MockOracleClient::new(&s.env, &oracle_id);
+        oracle.initialize(&s.admin, &7, &300);
+        oracle.set_price(&Asset::Stellar(s.token.address.clone()), &10_000_000);
+        oracle.set_price(&Asset::Stellar(collateral.address.clone()), &20_000_000);
+        let config = OracleConfig {
+            oracle: oracle_id,
+            max_age: 3_600,
+        };
+        s.client.set_oracle_config(&s.admin, &config);
+
+        let borrower = new_borrower(&s);
+        collateral.mint(&borrower, &1_000_000_000);
+        let loan_id = s
+            .client
+            .request_secured_loan(&borrower, &1_200_000_000, &4, &1_000_000_000);
+        let liquidator = Address::generate(&s.env);
+        s.token.mint(&liquidator, &1_000_000_000);
+
+        // A healthy loan cannot be liquidated and its borrower keeps their score
+        assert!(s
+            .client
+            .try_liquidate(&liquidator, &loan_id, &315_000_000)
+            .is_err());
+        assert_eq!(s.credit.get_score_value(&borrower), 300);
+
+        oracle.set_price(&Asset::Stellar(collateral.address.clone()), &15_000_000);
+
+        // Zero and negative amounts are rejected outright
+        assert!(s.client.try_liquidate(&liquidator, &loan_id, &0).is_err());
+        assert!(s.client.try_liquidate(&liquidator, &loan_id, &-1).is_err());
+        assert!(s
+            .client
+            .try_liquidate(&liquidator, &loan_id, &i128::MIN)
+            .is_err());
+
+        // One installment leaves the loan under water, so it can be liquidated again
+        assert_eq!(s.client.liquidate(&liquidator, &loan_id, &315_000_000), 220_500_000);
+        assert_eq!(s.client.get_loan(&loan_id).paid_payments, 1);
+        assert!(s.client.get_health_factor(&loan_id) < 10_000);
+        assert_eq!(s.credit.get_score_value(&borrower), 275);
+
+        s.client.liquidate(&liquidator, &loan_id, &315_000_000);
+        assert_eq!(s.client.get_loan(&loan_id).paid_payments, 2);
+        assert_eq!(s.client.get_collateral(&loan_id).unwrap().amount, 559_000_000);
+        assert_eq!(s.credit.get_score_value(&borrower), 275);
+    }
+
+    /// Register an identity registry and wire it to the loan manager
+    fn enable_identity_checks(s: &Setup) -> IdentityRegistryClient<'static> {
+        let registry_id = s.env.register(IdentityRegistry, ());
+        let registry = IdentityRegistryClient::new(&s.env, &registry_id);
+        registry.initialize(&s.admin);
+        s.client.set_identity_registry(&s.admin, &registry_id);
+        registry
+    }
+
+    /// Give `user` an identity verified at `level`
+    fn verify_identity(
+        s: &Setup,
+        registry: &IdentityRegistryClient,
+        user: &Address,
+        level: VerificationLevel,
+    ) {
+        registry.create_identity(
+            user,
+            &String::from_str(&s.env, "12345678"),
+            &String::from_str(&s.env, "bafy"),
+        );
+        registry.verify_user(&s.admin, user, &level);
+    }
+
+    #[test]
+    fn test_p2p_offer_flow() {
+        let s = setup();
+        let lender = Address::generate(&s.env);
+        s.token.mint(&lender, &200_000_000);
+
+        let offer_id = s.client.create_offer(
+            &lender,
+            &100_000_000,
+            &1_000,
+            &2,
+            &300,
+            &VerificationLevel::Level1,
+        );
+        assert_eq!(s.token.balance(&lender), 100_000_000);
+        assert_eq!(s.client.get_escrowed(&s.token.address), 100_000_000);
+
+        // An unverified borrower does not meet the offer requirements
+        let borrower = new_borrower(&s);
+        assert!(s.client.try_accept_offer(&borrower, &offer_id).is_err());
+
+        let registry = enable_identity_checks(&s);
+        verify_identity(&s, &registry, &borrower, VerificationLevel::Level1);
+        let loan_id = s.client.accept_offer(&borrower, &offer_id);
+
+        // The principal comes from the lender's escrow, not from minting
+        assert_eq!(s.token.balance(&borrower), 100_000_000);
+        assert_eq!(s.client.get_escrowed(&s.token.address), 0);
+        assert_eq!(s.client.get_loan_lender(&loan_id), Some(lender.clone()));
+
+        let offer = s.client.get_offer(&offer_id);
+        assert_eq!(offer.status, OfferStatus::Filled);
+        assert_eq!(offer.loan_id, Some(loan_id));
+        assert!(s.client.try_cancel_offer(&lender, &offer_id).is_err());
+
+        // The loan is priced at the offer rate and repaid to the lender
+        let loan = s.client.get_loan(&loan_id);
+        assert_eq!(loan.total_amount, 110_000_000);
+
+        s.token.mint(&borrower, &10_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(s.token.balance(&lender), 210_000_000);
+        assert_eq!(s.token.balance(&s.client.address), 0);
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Repaid);
+    }
+
+    #[test]
+    fn test_offer_requirements_and_cancel() {
+        let s = setup();
+        let lender = Address::generate(&s.env);
+        s.token.mint(&lender, &50_000_000);
+
+        let offer_id = s.client.create_offer(
+            &lender,
+            &50_000_000,
+            &800,
+            &1,
+            &400,
+            &VerificationLevel::Unverified,
+        );
+
+        // A new borrower starts at 300 points
+        let borrower = new_borrower(&s);
+        assert!(s.client.try_accept_offer(&borrower, &offer_id).is_err());
+        assert!(s.client.try_accept_offer(&lender, &offer_id).is_err());
+
+        let other = Address::generate(&s.env);
+        assert!(s.client.try_cancel_offer(&other, &offer_id).is_err());
+
+        s.client.cancel_offer(&lender, &offer_id);
+        assert_eq!(s.token.balance(&lender), 50_000_000);
+        assert_eq!(s.client.get_offer(&offer_id).status, OfferStatus::Cancelled);
+        assert!(s.client.try_accept_offer(&borrower, &offer_id).is_err());
+        assert_eq!(s.client.get_total_offers(), 1);
+    }
+
+    #[test]
+    fn test_request_funded_pro_rata_by_several_lenders() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let request_id = s.client.post_request(
+            &borrower,
+            &100_000_000,
+            &1_000,
+            &2,
+            &String::from_str(&s.env, "bafy-purpose"),
+            &1_000,
+        );
+
+        let lender_a = Address::generate(&s.env);
+        let lender_b = Address::generate(&s.env);
+        s.token.mint(&lender_a, &60_000_000);
+        s.token.mint(&lender_b, &60_000_000);
+
+        // The loan exists from the start but stays pending, out of the stats, until funded
+        let loan_id = s.client.get_request(&request_id).loan_id.unwrap();
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Pending);
+        assert!(s.client.try_pay_installment(&borrower, &loan_id).is_err());
+        assert_eq!(s.client.get_stats().active_count, 0);
+        assert_eq!(s.client.get_loan_id(&borrower), None);
+
+        assert_eq!(s.client.fund_request(&lender_a, &request_id, &60_000_000), 60_000_000);
+        assert_eq!(s.client.get_request(&request_id).status, RequestStatus::Open);
+        assert_eq!(s.token.balance(&borrower), 0);
+
+        // The second lender only commits what is missing, which disburses the loan
+        assert_eq!(s.client.fund_request(&lender_b, &request_id, &60_000_000), 40_000_000);
+        assert_eq!(s.token.balance(&lender_b), 20_000_000);
+        assert_eq!(s.token.balance(&borrower), 100_000_000);
+        assert_eq!(s.client.get_escrowed(&s.token.address), 0);
+
+        let request = s.client.get_request(&request_id);
+        assert_eq!(request.status, RequestStatus::Disbursed);
+        assert_eq!(request.loan_id, Some(loan_id));
+        let loan = s.client.get_loan(&loan_id);
+        assert_eq!(loan.status, LoanStatus::Active);
+        assert_eq!(loan.total_amount, 110_000_000);
+        assert_eq!(s.client.get_stats().active_count, 1);
+        assert_eq!(s.client.get_loan_id(&borrower), Some(loan_id));
+        assert_eq!(s.client.get_loan_shares(&loan_id).get(lender_a.clone()), Some(60_000_000));
+
+        // Each 55 USDC installment is split 60/40
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(s.token.balance(&lender_a), 33_000_000);
+        assert_eq!(s.token.balance(&lender_b), 20_000_000 + 22_000_000);
+
+        assert!(s.client.try_reclaim_funding(&lender_a, &request_id).is_err());
+    }
+
+    #[test]
+    fn test_reclaim_unfunded_request() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let cid = String::from_str(&s.env, "bafy-purpose");
+        let request_id = s
+            .client
+            .post_request(&borrower, &100_000_000, &1_000, &1, &cid, &1_000);
+
+        let lender = Address::generate(&s.env);
+        s.token.mint(&lender, &30_000_000);
+        s.client.fund_request(&lender, &request_id, &30_000_000);
+
+        // Funds stay committed until the deadline passes
+        assert!(s.client.try_reclaim_funding(&lender, &request_id).is_err());
+
+        s.env.ledger().with_mut(|li| li.timestamp = 1_001);
+        assert!(s
+            .client
+            .try_fund_request(&lender, &request_id, &1_000_000)
+            .is_err());
+        assert_eq!(s.client.reclaim_funding(&lender, &request_id), 30_000_000);
+        assert_eq!(s.token.balance(&lender), 30_000_000);
+        assert!(s.client.try_reclaim_funding(&lender, &request_id).is_err());
+        let loan_id = s.client.get_request(&request_id).loan_id.unwrap();
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Cancelled);
+
+        // A cancelled request can be reclaimed before its deadline
+        let other_request = s
+            .client
+            .post_request(&borrower, &50_000_000, &1_000, &1, &cid, &5_000);
+        s.client.fund_request(&lender, &other_request, &10_000_000);
+        s.client.cancel_request(&borrower, &other_request);
+        let loan_id = s.client.get_request(&other_request).loan_id.unwrap();
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Cancelled);
+        assert_eq!(s.client.get_stats(), PortfolioStats::default());
+        assert!(s
+            .client
+            .try_fund_request(&lender, &other_request, &1_000_000)
+            .is_err());
+        assert_eq!(s.client.reclaim_funding(&lender, &other_request), 10_000_000);
+        assert_eq!(s.client.get_escrowed(&s.token.address), 0);
+        assert_eq!(s.client.get_total_requests(), 2);
+    }
+
+    #[test]
+    fn test_request_refunded_when_borrower_becomes_ineligible() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let cid = String::from_str(&s.env, "bafy-purpose");
+        let request_id = s
+            .client
+            .post_request(&borrower, &100_000_000, &1_000, &2, &cid, &1_000);
+
+        let lender_a = Address::generate(&s.env);
+        let lender_b = Address::generate(&s.env);
+        s.token.mint(&lender_a, &60_000_000);
+        s.token.mint(&lender_b, &60_000_000);
+        s.client.fund_request(&lender_a, &request_id, &60_000_000);
+
+        // The borrower is blacklisted while the request is open
+        s.credit.penalize_user(&s.admin, &borrower, &1_000);
+
+        // The last contribution cancels the request instead of leaving the escrow stuck
+        assert_eq!(s.client.fund_request(&lender_b, &request_id, &60_000_000), 0);
+        let request = s.client.get_request(&request_id);
+        assert_eq!(request.status, RequestStatus::Cancelled);
+        assert_eq!(request.funded, 0);
+        let loan = s.client.get_loan(&request.loan_id.unwrap());
+        assert_eq!(loan.status, LoanStatus::Cancelled);
+        assert_eq!(s.token.balance(&lender_a), 60_000_000);
+        assert_eq!(s.token.balance(&lender_b), 60_000_000);
+        assert_eq!(s.token.balance(&borrower), 0);
+        assert_eq!(s.client.get_escrowed(&s.token.address), 0);
+        assert!(s.client.get_request_funders(&request_id).is_empty());
+        assert!(s.client.try_reclaim_funding(&lender_a, &request_id).is_err());
+        assert!(s
+            .client
+            .try_fund_request(&lender_a, &request_id, &1_000_000)
+            .is_err());
+    }
+
+    /// Register a lending pool wired to the loan manager, with senior and junior deposits
+    fn enable_lending_pool(
+        s: &Setup,
+        senior: i128,
+        junior: i128,
+    ) -> LendingPoolMiniClient<'static> {
+        let pool_id = s.env.register(LendingPoolMini, ());
+        let pool = LendingPoolMiniClient::new(&s.env, &pool_id);
+        pool.initialize(&s.admin, &s.token.address);
+        for tranche in [Tranche::Senior, Tranche::Junior] {
+            let share_token = s.env.register(TrancheShareToken, ());
+            TrancheShareTokenClient::new(&s.env, &share_token).initialize(
+                &pool_id,
+                &String::from_str(&s.env, "lpUSDC"),
+                &String::from_str(&s.env, "lpUSDC"),
+                &6u32,
+            );
+            pool.set_share_token(&tranche, &share_token);
+        }
+        pool.set_loan_manager(&s.client.address);
+        s.client.set_lending_pool(&s.admin, &pool_id);
+
+        let depositor = Address::generate(&s.env);
+        s.token.mint(&depositor, &(senior + junior));
+        pool.deposit(&depositor, &Tranche::Senior, &senior);
+        pool.deposit(&depositor, &Tranche::Junior, &junior);
+        pool
+    }
+
+    #[test]
+    fn test_pool_funded_loans() {
+        let s = setup();
+        let pool = enable_lending_pool(&s, 800_000_000, 200_000_000);
+        let supply_before = s.token.total_supply();
+
+        // Disbursed from pool cash rather than minted
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.request_loan(&borrower, &100_000_000, &1, &None);
+        assert_eq!(s.token.total_supply(), supply_before);
+        assert_eq!(pool.get_total_liquidity(), 900_000_000);
+        assert_eq!(pool.get_lent_out(), 100_000_000);
+
+        // 5 USDC of interest is split 70/30 between the tranches
+        s.token.mint(&borrower, &5_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(pool.get_lent_out(), 0);
+        assert_eq!(pool.get_tranche(&Tranche::Senior).assets, 803_500_000);
+        assert_eq!(pool.get_tranche(&Tranche::Junior).assets, 201_500_000);
+
+        // A written-off default is absorbed by the junior tranche
+        let other = new_borrower(&s);
+        let defaulted = s.client.request_loan(&other, &150_000_000, &1, &None);
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = (PAYMENT_PERIOD + GRACE_PERIOD + 1) as u64);
+        s.client.mark_defaulted(&defaulted);
+        assert_eq!(pool.get_lent_out(), 150_000_000);
+        s.client.write_off(&s.admin, &defaulted);
+        assert_eq!(pool.get_lent_out(), 0);
+        assert_eq!(pool.get_tranche(&Tranche::Junior).assets, 51_500_000);
+        assert_eq!(pool.get_tranche(&Tranche::Senior).assets, 803_500_000);
+    }
+
+    #[test]
+    fn test_minted_write_off_keeps_the_reserve() {
+        let s = setup();
+        s.client.set_reserve_rate(&s.admin, &10_000);
+
+        let payer = new_borrower(&s);
+        let repaid = s.client.request_loan(&payer, &100_000_000, &1, &None);
+        s.token.mint(&payer, &5_000_000);
+        s.client.pay_installment(&payer, &repaid);
+        assert_eq!(s.client.reserve_balance(), 5_000_000);
+
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.request_loan(&borrower, &10_000_000, &1, &None);
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = (PAYMENT_PERIOD + GRACE_PERIOD + 1) as u64);
+        s.client.mark_defaulted(&loan_id);
+
+        // No lender is owed the minted principal, so no reserve turns into fees
+        let withdrawable = s.client.get_withdrawable_fees();
+        assert_eq!(s.client.write_off(&s.admin, &loan_id), 0);
+        assert_eq!(s.client.reserve_balance(), 5_000_000);
+        assert_eq!(s.client.get_withdrawable_fees(), withdrawable);
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::WrittenOff);
+    }
+
+    #[test]
+    fn test_loss_reserve_covers_write_off() {
+        let s = setup();
+        let pool = enable_lending_pool(&s, 800_000_000, 200_000_000);
+        s.client.set_reserve_rate(&s.admin, &2_000);
+
+        // 20% of the 5 USDC of interest goes to the reserve, the rest to the pool
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.request_loan(&borrower, &100_000_000, &1, &None);
+        s.token.mint(&borrower, &5_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(s.client.reserve_balance(), 1_000_000);
+        assert_eq!(s.token.balance(&s.client.address), 1_000_000);
+        assert_eq!(pool.get_total_liquidity(), 1_004_000_000);
+
+        let other = new_borrower(&s);
+        let defaulted = s.client.request_loan(&other, &10_000_000, &2, &None);
+
+        // Only defaulted loans can be written off, and only by an admin
+        assert!(s.client.try_write_off(&s.admin, &defaulted).is_err());
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = (PAYMENT_PERIOD + GRACE_PERIOD + 1) as u64);
+        s.client.mark_defaulted(&defaulted);
+        let outsider = Address::generate(&s.env);
+        assert!(s.client.try_write_off(&outsider, &defaulted).is_err());
+
+        // The reserve covers 1 of the 10 USDC shortfall, the junior tranche the rest
+        let junior_before = pool.get_tranche(&Tranche::Junior).assets;
+        assert_eq!(s.client.write_off(&s.admin, &defaulted), 1_000_000);
+        assert_eq!(s.client.reserve_balance(), 0);
+        assert_eq!(pool.get_tranche(&Tranche::Junior).assets, junior_before - 9_000_000);
+        assert_eq!(pool.get_lent_out(), 0);
+        assert_eq!(s.client.get_loan(&defaulted).status, LoanStatus::WrittenOff);
+        assert!(s.client.try_write_off(&s.admin, &defaulted).is_err());
+    }
+
+    #[test]
+    fn test_fees_and_treasury_withdrawal() {
+        let s = setup();
+        let config = FeeConfig {
+            origination_fee_bps: 100,
+            protocol_fee_bps: 1_000,
+        };
+        s.client.set_fee_config(&s.admin, &config);
+
+        let lender = Address::generate(&s.env);
+        s.token.mint(&lender, &150_000_000);
+        let offer_id = s.client.create_offer(
+            &lender,
+            &100_000_000,
+            &1_000,
+            &1,
+            &0,
+            &VerificationLevel::Unverified,
+        );
+        // A second open offer keeps 50 USDC of lender money in escrow
+        s.client.create_offer(
+            &lender,
+            &50_000_000,
+            &1_000,
+            &1,
+            &0,
+            &VerificationLevel::Unverified,
+        );
+
+        // 1% of the principal is kept at disbursement
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.accept_offer(&borrower, &offer_id);
+        assert_eq!(s.token.balance(&borrower), 99_000_000);
+        assert_eq!(s.client.get_loan(&loan_id).amount, 100_000_000);
+
+        // 10% of the 10 USDC of interest goes to the protocol, the rest to the lender
+        s.token.mint(&borrower, &11_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(s.token.balance(&lender), 109_000_000);
+        assert_eq!(s.client.get_fees_collected(), 2_000_000);
+        assert_eq!(s.client.get_withdrawable_fees(), 2_000_000);
+
+        // Fees only go to the treasury, only up to what the protocol owns
+        let treasury = Address::generate(&s.env);
+        assert!(s
+            .client
+            .try_withdraw_fees(&s.admin, &treasury, &1_000_000)
+            .is_err());
+        s.client.set_treasury(&s.admin, &treasury);
+
+        let treasurer = Address::generate(&s.env);
+        assert!(s
+            .client
+            .try_withdraw_fees(&treasurer, &treasury, &1_000_000)
+            .is_err());
+        s.client.grant_role(&s.admin, &Role::Treasurer, &treasurer);
+        assert!(s
+            .client
+            .try_withdraw_fees(&treasurer, &treasurer, &1_000_000)
+            .is_err());
+        assert!(s
+            .client
+            .try_withdraw_fees(&treasurer, &treasury, &2_000_001)
+            .is_err());
+
+        s.client.withdraw_fees(&treasurer, &treasury, &2_000_000);
+        assert_eq!(s.token.balance(&treasury), 2_000_000);
+        assert_eq!(s.client.get_withdrawable_fees(), 0);
+        assert_eq!(s.token.balance(&s.client.address), 50_000_000);
+    }
+
+    #[test]
+    fn test_reserve_pays_p2p_lender_and_is_kept_from_fees() {
+        let s = setup();
+        let config = FeeConfig {
+            origination_fee_bps: 0,
+            protocol_fee_bps: 1_000,
+        };
+        s.client.set_fee_config(&s.admin, &config);
+        s.client.set_reserve_rate(&s.admin, &2_000);
+        let treasury = Address::generate(&s.env);
+        s.client.set_treasury(&s.admin, &treasury);
+
+        let lender = Address::generate(&s.env);
+        s.token.mint(&lender, &150_000_000);
+        let repaid_offer = s.client.create_offer(
+            &lender,
+            &100_000_000,
+            &1_000,
+            &1,
+            &0,
+            &VerificationLevel::Unverified,
+        );
+        let defaulting_offer = s.client.create_offer(
+            &lender,
+            &50_000_000,
+            &1_000,
+            &1,
+            &0,
+            &VerificationLevel::Unverified,
+        );
+
+        // Of the 10 USDC of interest, 2 go to the reserve and 1 to the protocol
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.accept_offer(&borrower, &repaid_offer);
+        s.token.mint(&borrower, &10_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(s.token.balance(&lender), 107_000_000);
+        assert_eq!(s.client.reserve_balance(), 2_000_000);
+
+        // The contract holds 53 USDC, but only the protocol's 1 can be withdrawn: the open
+        // offer is escrowed and the reserve belongs to the lenders
+        assert_eq!(s.token.balance(&s.client.address), 53_000_000);
+        assert_eq!(s.client.get_escrowed(&s.token.address), 50_000_000);
+        assert_eq!(s.client.get_withdrawable_fees(), 1_000_000);
+        assert!(s
+            .client
+            .try_withdraw_fees(&s.admin, &treasury, &1_000_001)
+            .is_err());
+
+        // The reserve pays the lender 2 of the 50 USDC lost on the other offer
+        let other = new_borrower(&s);
+        let defaulted = s.client.accept_offer(&other, &defaulting_offer);
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = (PAYMENT_PERIOD + GRACE_PERIOD + 1) as u64);
+        s.client.mark_defaulted(&defaulted);
+        assert_eq!(s.client.write_off(&s.admin, &defaulted), 2_000_000);
+        assert_eq!(s.token.balance(&lender), 109_000_000);
+        assert_eq!(s.client.reserve_balance(), 0);
+
+        s.client.withdraw_fees(&s.admin, &treasury, &1_000_000);
+        assert_eq!(s.token.balance(&s.client.address), 0);
+    }
+
+    #[test]
+    fn test_origination_fee_on_minted_loans() {
+        let s = setup();
+        let config = FeeConfig {
+            origination_fee_bps: 200,
+            protocol_fee_bps: 0,
+        };
+        s.client.set_fee_config(&s.admin, &config);
+
+        // Repayments of minted loans are protocol funds and can be withdrawn
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.request_loan(&borrower, &100_000_000, &1, &None);
+        assert_eq!(s.token.balance(&borrower), 98_000_000);
+        assert_eq!(s.client.get_withdrawable_fees(), 2_000_000);
+
+        s.token.mint(&borrower, &7_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(s.client.get_withdrawable_fees(), 107_000_000);
+        assert_eq!(s.client.get_fees_collected(), 2_000_000);
+    }
+
+    #[test]
+    fn test_restructure_and_forbearance() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.request_loan(&borrower, &120_000_000, &2, &None);
+        s.client.pay_installment(&borrower, &loan_id);
+
+        let outsider = Address::generate(&s.env);
+        assert!(s
+            .client
+            .try_restructure_loan(&outsider, &loan_id, &3, &200)
+            .is_err());
+
+        // The 60 USDC of unpaid principal is spread over 3 installments at 2%
+        s.env.ledger().with_mut(|li| li.timestamp = 1_000);
+        s.client.restructure_loan(&s.admin, &loan_id, &3, &200);
+        let loan = s.client.get_loan(&loan_id);
+        assert_eq!(loan.amount, 60_000_000);
+        assert_eq!(loan.total_amount, 61_200_000);
+        assert_eq!(loan.payment_amount, 20_400_000);
+        assert_eq!(loan.paid_payments, 0);
+        assert_eq!(s.client.get_next_due_date(&loan_id), 1_000 + PAYMENT_PERIOD);
+        assert_eq!(s.client.get_stats().outstanding_principal, 60_000_000);
+
+        // Two periods of forbearance push the next due date back
+        s.client.grant_forbearance(&s.admin, &loan_id, &2);
+        let due_date = s.client.get_next_due_date(&loan_id);
+        assert_eq!(due_date, 1_000 + 3 * PAYMENT_PERIOD);
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = (1_000 + PAYMENT_PERIOD + GRACE_PERIOD + 1) as u64);
+        assert!(s.client.try_mark_defaulted(&loan_id).is_err());
+
+        let history = s.client.get_modifications(&loan_id);
+        assert_eq!(history.len(), 2);
+        let restructure = history.get(0).unwrap();
+        assert_eq!(restructure.kind, ModificationKind::Restructure);
+        assert_eq!(restructure.debt_before, 63_000_000);
+        assert_eq!(restructure.debt_after, 61_200_000);
+        assert_eq!(history.get(1).unwrap().periods, 2);
+
+        // Each modification costs less score than the 50 points of a default
+        assert_eq!(s.credit.get_score_value(&borrower), 300 - 2 * 15);
+
+        // The new schedule repays the remaining principal
+        s.token.mint(&borrower, &4_200_000);
+        for _ in 0..3 {
+            s.client.pay_installment(&borrower, &loan_id);
+        }
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Repaid);
+        assert_eq!(s.client.get_stats().outstanding_principal, 0);
+    }
+
+    #[test]
+    fn test_modifying_lender_funded_loans_needs_their_consent() {
+        let s = setup();
+        let lender = Address::generate(&s.env);
+        s.token.mint(&lender, &100_000_000);
+        let offer_id = s.client.create_offer(
+            &lender,
+            &100_000_000,
+            &1_000,
+            &2,
+            &0,
+            &VerificationLevel::Unverified,
+        );
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.accept_offer(&borrower, &offer_id);
+
+        // The rate is capped at 100%
+        assert!(s
+            .client
+            .try_restructure_loan(&s.admin, &loan_id, &3, &10_001)
+            .is_err());
+        assert!(s
+            .client
+            .try_restructure_loan(&s.admin, &loan_id, &3, &-1)
+            .is_err());
+
+        // Borrower and risk manager alone cannot change what the lender is owed
+        let args = (s.admin.clone(), loan_id, 3u32, 0i128).into_val(&s.env);
+        let invoke = MockAuthInvoke {
+            contract: &s.client.address,
+            fn_name: "restructure_loan",
+            args,
+            sub_invokes: &[],
+        };
+        s.env.mock_auths(&[
+            MockAuth {
+                address: &s.admin,
+                invoke: &invoke,
+            },
+            MockAuth {
+                address: &borrower,
+                invoke: &invoke,
+            },
+        ]);
+        assert!(s
+            .client
+            .try_restructure_loan(&s.admin, &loan_id, &3, &0)
+            .is_err());
+
+        let args = (s.admin.clone(), loan_id, 1u32).into_val(&s.env);
+        let invoke = MockAuthInvoke {
+            contract: &s.client.address,
+            fn_name: "grant_forbearance",
+            args,
+            sub_invokes: &[],
+        };
+        s.env.mock_auths(&[
+            MockAuth {
+                address: &s.admin,
+                invoke: &invoke,
+            },
+            MockAuth {
+                address: &borrower,
+                invoke: &invoke,
+            },
+        ]);
+        assert!(s.client.try_grant_forbearance(&s.admin, &loan_id, &1).is_err());
+
+        // With the lender on board both go through
+        s.env.mock_all_auths();
+        let due_date = s.client.get_next_due_date(&loan_id);
+        s.client.grant_forbearance(&s.admin, &loan_id, &1);
+        assert_eq!(s.client.get_next_due_date(&loan_id), due_date + PAYMENT_PERIOD);
+        s.client.restructure_loan(&s.admin, &loan_id, &4, &500);
+        let loan = s.client.get_loan(&loan_id);
+        assert_eq!(loan.interest_rate, 500);
+        assert_eq!(loan.total_amount, 105_000_000);
+
+        // A request-funded loan needs every one of its lenders
+        let cid = String::from_str(&s.env, "bafy-purpose");
+        let other = new_borrower(&s);
+        let request_id = s
+            .client
+            .post_request(&other, &100_000_000, &1_000, &2, &cid, &1_000);
+        let lender_b = Address::generate(&s.env);
+        s.token.mint(&lender, &50_000_000);
+        s.token.mint(&lender_b, &50_000_000);
+        s.client.fund_request(&lender, &request_id, &50_000_000);
+        s.client.fund_request(&lender_b, &request_id, &50_000_000);
+        let shared_loan = s.client.get_request(&request_id).loan_id.unwrap();
+
+        let args = (s.admin.clone(), shared_loan, 3u32, 0i128).into_val(&s.env);
+        let invoke = MockAuthInvoke {
+            contract: &s.client.address,
+            fn_name: "restructure_loan",
+            args,
+            sub_invokes: &[],
+        };
+        s.env.mock_auths(&[
+            MockAuth {
+                address: &s.admin,
+                invoke: &invoke,
+            },
+            MockAuth {
+                address: &other,
+                invoke: &invoke,
+            },
+            MockAuth {
+                address: &lender,
+                invoke: &invoke,
+            },
+        ]);
+        assert!(s
+            .client
+            .try_restructure_loan(&s.admin, &shared_loan, &3, &0)
+            .is_err());
+        s.env.mock_all_auths();
+        assert_eq!(s.client.get_loan(&shared_loan).interest_rate, 1_000);
+    }
+
+    #[test]
+    fn test_refinance_at_current_rate() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.request_loan(&borrower, &120_000_000, &2, &None);
+        assert_eq!(s.client.get_loan(&loan_id).interest_rate, 500);
+        s.client.pay_installment(&borrower, &loan_id);
+
+        // Rates tiered by score, for loans and credit lines
+        let mut tiers = Vec::new(&s.env);
+        tiers.push_back(RateTier {
+            min_score: 300,
+            rate: 200,
+            line_rate: 900,
+        });
+        tiers.push_back(RateTier {
+            min_score: 400,
+            rate: 100,
+            line_rate: 600,
+        });
+        assert!(s.client.try_set_rate_tiers(&borrower, &tiers).is_err());
+        let mut invalid = tiers.clone();
+        invalid.push_back(RateTier {
+            min_score: 500,
+            rate: -1,
+            line_rate: 600,
+        });
+        assert!(s.client.try_set_rate_tiers(&s.admin, &invalid).is_err());
+        s.client.set_rate_tiers(&s.admin, &tiers);
+        assert_eq!(s.client.get_rate_for(&borrower), 200);
+        let low_score = new_borrower(&s);
+        s.credit.penalize_user(&s.admin, &low_score, &50);
+        assert_eq!(s.client.get_rate_for(&low_score), 500);
+        assert_eq!(s.client.get_line_rate_for(&low_score), 1_200);
+
+        // The borrower's score has since improved into the best tier
+        s.credit.reward(&s.admin, &borrower, &100);
+        assert_eq!(s.client.get_rate_for(&borrower), 100);
+        assert_eq!(s.client.get_line_rate_for(&borrower), 600);
+
+        // The minted loan is rolled over by netting: nothing is minted or moved
+        let supply = s.token.total_supply();
+        let withdrawable = s.client.get_withdrawable_fees();
+        let balance = s.token.balance(&borrower);
+        let new_loan_id = s.client.refinance(&loan_id, &3);
+        assert_eq!(s.token.balance(&borrower), balance);
+        assert_eq!(s.token.total_supply(), supply);
+        assert_eq!(s.client.get_withdrawable_fees(), withdrawable);
+
+        let old_loan = s.client.get_loan(&loan_id);
+        assert_eq!(old_loan.status, LoanStatus::Repaid);
+        assert_eq!(s.client.get_refinanced_into(&loan_id), Some(new_loan_id));
+
+        // The 60 USDC of unpaid principal rolls into the new loan at the borrower's new 1%
+        let new_loan = s.client.get_loan(&new_loan_id);
+        assert_eq!(new_loan.amount, 60_000_000);
+        assert_eq!(new_loan.interest_rate, 100);
+        assert_eq!(new_loan.total_amount, 60_600_000);
+        assert_eq!(new_loan.num_payments, 3);
+
+        let stats = s.client.get_stats();
+        assert_eq!(stats.total_disbursed, 180_000_000);
+        assert_eq!(stats.outstanding_principal, 60_000_000);
+        assert_eq!(stats.active_count, 1);
+        assert_eq!(stats.repaid_count, 1);
+
+        // A credit line opened now gets the tier's annual rate
+        assert_eq!(s.client.open_credit_line(&borrower).rate, 600);
+
+        // A loan with an overdue installment cannot be refinanced
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = (PAYMENT_PERIOD + 1) as u64);
+        assert!(s.client.try_refinance(&new_loan_id, &3).is_err());
+
+        // Nor can a loan whose guarantor or merchant is tied to its id
+        let guarantor = new_borrower(&s);
+        let guaranteed = s
+            .client
+            .request_loan(&new_borrower(&s), &10_000_000, &2, &Some(guarantor));
+        assert!(s.client.try_refinance(&guaranteed, &3).is_err());
+
+        let merchant = Address::generate(&s.env);
+        let name = String::from_str(&s.env, "Shop");
+        s.client.register_merchant(&s.admin, &merchant, &name, &300);
+        let purchase = s
+            .client
+            .bnpl_purchase(&new_borrower(&s), &merchant, &10_000_000, &2);
+        assert!(s.client.try_refinance(&purchase, &3).is_err());
+    }
+
+    #[test]
+    fn test_credit_line_draw_repay_and_minimum_payment() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+
+        assert_eq!(s.client.get_line_rate(), 1_200);
+        assert!(s.client.try_set_line_rate(&borrower, &500).is_err());
+        assert!(s.client.try_set_line_rate(&s.admin, &10_001).is_err());
+        s.client.set_line_rate(&s.admin, &500);
+
+        let line = s.client.open_credit_line(&borrower);
+        assert_eq!(line.limit, 999_999_900);
+        assert_eq!(line.rate, 500);
+        assert_eq!(s.client.get_stats().active_count, 1);
+        assert!(s.client.try_open_credit_line(&borrower).is_err());
+        assert!(s.client.try_draw(&borrower, &1_000_000_000).is_err());
+
+        s.client.draw(&borrower, &100_000_000);
+        assert_eq!(s.token.balance(&borrower), 100_000_000);
+        assert_eq!(s.client.get_stats().outstanding_principal, 100_000_000);
+
+        // 30 days of 5% a year on 100 USDC, and a first statement of 10% of the balance
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = PAYMENT_PERIOD as u64);
+        let line = s.client.get_credit_line(&borrower);
+        assert_eq!(line.interest, 410_958);
+        assert_eq!(line.min_due, 10_041_095);
+        assert_eq!(line.next_due, 2 * PAYMENT_PERIOD as u64);
+
+        // Interest is repaid first
+        assert_eq!(s.client.repay(&borrower, &10_041_095), 10_041_095);
+        let line = s.client.get_credit_line(&borrower);
+        assert_eq!(line.interest, 0);
+        assert_eq!(line.drawn, 90_369_863);
+        assert_eq!(line.min_due, 0);
+        assert_eq!(s.client.get_stats().interest_earned, 410_958);
+        assert!(s.client.try_close_credit_line(&borrower).is_err());
+
+        // The next statement goes unpaid past the grace period
+        let deadline = (3 * PAYMENT_PERIOD + GRACE_PERIOD) as u64;
+        s.env.ledger().with_mut(|li| li.timestamp = deadline);
+        assert!(s.client.try_draw(&borrower, &1_000_000).is_err());
+        assert!(s.client.try_mark_line_defaulted(&borrower).is_err());
+
+        s.env.ledger().with_mut(|li| li.timestamp = deadline + 1);
+        s.client.mark_line_defaulted(&borrower);
+        let line = s.client.get_credit_line(&borrower);
+        assert_eq!(line.status, CreditLineStatus::Defaulted);
+        assert_eq!(s.credit.get_score_value(&borrower), 250);
+        let stats = s.client.get_stats();
+        assert_eq!(stats.outstanding_principal, 0);
+        assert_eq!(stats.active_count, 0);
+        assert_eq!(stats.defaulted_count, 1);
+        assert!(s.client.try_repay(&borrower, &1_000_000).is_err());
+    }
+
+    #[test]
+    fn test_repaid_credit_line_can_close() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        s.client.open_credit_line(&borrower);
+        s.client.draw(&borrower, &50_000_000);
+
+        s.env.ledger().with_mut(|li| li.timestamp = 1_000);
+        s.token.mint(&borrower, &1_000_000);
+        // 1000 seconds at the default 12% a year
+        let paid = s.client.repay(&borrower, &60_000_000);
+        assert_eq!(paid, 50_000_000 + 190);
+        assert_eq!(s.token.balance(&borrower), 1_000_000 - 190);
+
+        // No balance means no statement and nothing due
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = (5 * PAYMENT_PERIOD) as u64);
+        let line = s.client.get_credit_line(&borrower);
+        assert_eq!(line.min_due, 0);
+        assert_eq!(line.next_due, 6 * PAYMENT_PERIOD as u64);
+
+        s.client.close_credit_line(&borrower);
+        assert_eq!(s.client.get_credit_line(&borrower).status, CreditLineStatus::Closed);
+        assert_eq!(s.client.get_stats().active_count, 0);
+        s.client.open_credit_line(&borrower);
+        assert_eq!(s.client.get_stats().active_count, 1);
+    }
+
+    #[test]
+    fn test_credit_line_write_off() {
+        let s = setup();
+        s.client.set_reserve_rate(&s.admin, &10_000);
+
+        // A repaid loan puts its 5 USDC of interest in the reserve
+        let borrower = new_borrower(&s);
+        let loan_id = s.client.request_loan(&borrower, &100_000_000, &1, &None);
+        s.token.mint(&borrower, &5_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(s.client.reserve_balance(), 5_000_000);
+
+        // One line is minted, the other funded by the pool
+        let minted = new_borrower(&s);
+        s.client.open_credit_line(&minted);
+        s.client.draw(&minted, &10_000_000);
+        let pool = enable_lending_pool(&s, 800_000_000, 200_000_000);
+        let pooled = new_borrower(&s);
+        s.client.open_credit_line(&pooled);
+        s.client.draw(&pooled, &10_000_000);
+
+        // Neither pays its first statement
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = (2 * PAYMENT_PERIOD + GRACE_PERIOD + 1) as u64);
+        assert!(s.client.try_write_off_line(&s.admin, &minted).is_err());
+        s.client.mark_line_defaulted(&minted);
+        s.client.mark_line_defaulted(&pooled);
+        let stats = s.client.get_stats();
+        assert_eq!(stats.active_count, 0);
+        assert_eq!(stats.defaulted_count, 2);
+
+        // The protocol absorbs its own line without touching the reserve
+        assert_eq!(s.client.write_off_line(&s.admin, &minted), 0);
+        assert_eq!(s.client.reserve_balance(), 5_000_000);
+        assert_eq!(
+            s.client.get_credit_line(&minted).status,
+            CreditLineStatus::WrittenOff
+        );
+
+        // The reserve covers half of the pool's line, the junior tranche the rest
+        assert_eq!(s.client.write_off_line(&s.admin, &pooled), 5_000_000);
+        assert_eq!(s.client.reserve_balance(), 0);
+        assert_eq!(pool.get_lent_out(), 0);
+        assert_eq!(pool.get_tranche(&Tranche::Junior).assets, 195_000_000);
+        assert!(s.client.try_write_off_line(&s.admin, &pooled).is_err());
+    }
+
+    #[test]
+    fn test_bnpl_purchase_and_refunds() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let merchant = Address::generate(&s.env);
+        let name = String::from_str(&s.env, "Tienda");
+
+        assert!(s
+            .client
+            .try_bnpl_purchase(&borrower, &merchant, &120_000_000, &4)
+            .is_err());
+        s.client.register_merchant(&s.admin, &merchant, &name, &300);
+
+        // The merchant is paid minus its 3% discount, which funds the zero-interest plan
+        let loan_id = s.client.bnpl_purchase(&borrower, &merchant, &120_000_000, &4);
+        assert_eq!(s.token.balance(&merchant), 116_400_000);
+        assert_eq!(s.token.balance(&borrower), 0);
+        assert_eq!(s.token.balance(&s.client.address), 3_600_000);
+        let loan = s.client.get_loan(&loan_id);
+        assert_eq!(loan.interest_rate, 0);
+        assert_eq!(loan.payment_amount, 30_000_000);
+
+        s.token.mint(&borrower, &30_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+
+        // A partial refund lowers the remaining installments, keeping their due dates
+        let outsider = Address::generate(&s.env);
+        assert!(s.client.try_refund_purchase(&outsider, &loan_id, &30_000_000).is_err());
+        let due_date = s.client.get_next_due_date(&loan_id);
+        s.client.refund_purchase(&merchant, &loan_id, &30_000_000);
+        let loan = s.client.get_loan(&loan_id);
+        assert_eq!(loan.payment_amount, 20_000_000);
+        assert_eq!(loan.paid_payments, 1);
+        assert_eq!(s.client.get_outstanding_debt(&loan_id), 60_000_000);
+        assert_eq!(s.client.get_next_due_date(&loan_id), due_date);
+        assert_eq!(s.client.get_stats().outstanding_principal, 60_000_000);
+        assert_eq!(s.token.balance(&borrower), 0);
+
+        // Refunding the rest closes the loan and gives back the installment already paid
+        assert!(s.client.try_refund_purchase(&merchant, &loan_id, &90_000_001).is_err());
+        s.token.mint(&merchant, &3_600_000);
+        s.client.refund_purchase(&merchant, &loan_id, &90_000_000);
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Repaid);
+        assert_eq!(s.client.get_purchase(&loan_id).unwrap().refunded, 120_000_000);
+        assert_eq!(s.client.get_stats().outstanding_principal, 0);
+        assert_eq!(s.token.balance(&merchant), 0);
+        assert_eq!(s.token.balance(&borrower), 30_000_000);
+        assert!(s.client.try_refund_purchase(&merchant, &loan_id, &1).is_err());
+    }
+
+    #[test]
+    fn test_bnpl_partial_refund_keeps_paid_installments() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let merchant = Address::generate(&s.env);
+        let name = String::from_str(&s.env, "Tienda");
+        s.client.register_merchant(&s.admin, &merchant, &name, &0);
+        let loan_id = s.client.bnpl_purchase(&borrower, &merchant, &100_000_000, &4);
+        let created_at = s.client.get_loan(&loan_id).created_at;
+
+        s.token.mint(&borrower, &100_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+
+        // 65M left over 3 installments: 21.666666M twice, the remainder in the last one
+        s.client.refund_purchase(&merchant, &loan_id, &10_000_000);
+        let loan = s.client.get_loan(&loan_id);
+        assert_eq!(loan.created_at, created_at);
+        assert_eq!(loan.paid_payments, 1);
+        assert_eq!(loan.num_payments, 4);
+        assert_eq!(loan.payment_amount, 21_666_666);
+        assert_eq!(s.client.get_outstanding_debt(&loan_id), 65_000_000);
+
+        s.client.pay_installment(&borrower, &loan_id);
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(s.client.get_outstanding_debt(&loan_id), 21_666_668);
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Repaid);
+        assert_eq!(s.token.balance(&borrower), 10_000_000);
+        let stats = s.client.get_stats();
+        assert_eq!(stats.outstanding_principal, 0);
+        assert_eq!(stats.total_repaid, 100_000_000);
+
+        // Refunds are blocked while repayments are paused
+        s.client.pause(&s.admin, &PauseFlag::Repayments);
+        assert!(s.client.try_refund_purchase(&merchant, &loan_id, &1).is_err());
+    }
+
+    #[test]
+    fn test_bnpl_refund_after_repayment_goes_to_borrower() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let merchant = Address::generate(&s.env);
+        let name = String::from_str(&s.env, "Tienda");
+        s.client.register_merchant(&s.admin, &merchant, &name, &0);
+        let loan_id = s.client.bnpl_purchase(&borrower, &merchant, &60_000_000, &2);
+
+        s.token.mint(&borrower, &60_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+        s.client.pay_installment(&borrower, &loan_id);
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Repaid);
+
+        let repaid = s.client.get_stats().total_repaid;
+        s.client.refund_purchase(&merchant, &loan_id, &60_000_000);
+        assert_eq!(s.token.balance(&borrower), 60_000_000);
+        assert_eq!(s.token.balance(&merchant), 0);
+        assert_eq!(s.client.get_stats().total_repaid, repaid);
+    }
+
+    #[test]
+    fn test_lending_circle_joint_liability() {
+        let s = setup();
+        let registry = enable_identity_checks(&s);
+        let mut members = Vec::new(&s.env);
+        for _ in 0..3 {
+            let member = new_borrower(&s);
+            verify_identity(&s, &registry, &member, VerificationLevel::Level1);
+            members.push_back(member);
+        }
+        let (m1, m2, m3) = (
+            members.get(0).unwrap(),
+            members.get(1).unwrap(),
+            members.get(2).unwrap(),
+        );
+
+        // Members must be verified and a circle needs at least 3 of them
+        let mut invalid = members.clone();
+        invalid.push_back(new_borrower(&s));
+        assert!(s.client.try_form_circle(&invalid, &5000).is_err());
+        invalid = members.clone();
+        invalid.pop_back();
+        assert!(s.client.try_form_circle(&invalid, &5000).is_err());
+
+        let circle_id = s.client.form_circle(&members, &5000);
+        assert_eq!(s.client.get_member_circle(&m1), Some(circle_id));
+        assert_eq!(s.client.get_circle_limit(&m1), 999_999_900);
+        assert!(s.client.try_form_circle(&members, &5000).is_err());
+
+        // A repaid circle loan raises the collective score; its interest funds the reserve
+        s.client.set_reserve_rate(&s.admin, &10000);
+        let loan_id = s.client.request_circle_loan(&m1, &999_000_000, &1);
+        s.token.mint(&m1, &49_950_000);
+        s.client.pay_installment(&m1, &loan_id);
+        assert_eq!(s.client.reserve_balance(), 49_950_000);
+        assert_eq!(s.client.get_circle(&circle_id).score, 110);
+
+        // m2 defaults on 60 USDC; m1 and m3 owe half of it between them
+        let loan_id = s.client.request_circle_loan(&m2, &60_000_000, &1);
+        let default_time = (PAYMENT_PERIOD + GRACE_PERIOD + 1) as u64;
+        s.env.ledger().with_mut(|li| li.timestamp = default_time);
+        s.client.mark_defaulted(&loan_id);
+        let obligation = s.client.get_joint_obligation(&loan_id).unwrap();
+        assert_eq!(obligation.owed.get(m1.clone()), Some(15_000_000));
+        assert_eq!(obligation.owed.get(m3.clone()), Some(15_000_000));
+        assert_eq!(obligation.owed.get(m2.clone()), None);
+        assert_eq!(s.client.get_circle(&circle_id).open_obligations, 1);
+        assert!(s.client.try_leave_circle(&m1).is_err());
+
+        s.token.mint(&m1, &15_000_000);
+        s.client.pause(&s.admin, &PauseFlag::Repayments);
+        assert!(s.client.try_pay_obligation(&m1, &loan_id).is_err());
+        s.client.unpause(&s.admin, &PauseFlag::Repayments);
+        assert_eq!(s.client.pay_obligation(&m1, &loan_id), 15_000_000);
+        assert!(s.client.try_settle_obligation(&loan_id).is_err());
+        assert!(s.client.try_write_off(&s.admin, &loan_id).is_err());
+
+        // m3 never pays, so the circle is penalized once the deadline passes
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = default_time + PAYMENT_PERIOD as u64 + 1);
+        s.client.settle_obligation(&loan_id);
+        let circle = s.client.get_circle(&circle_id);
+        assert_eq!(circle.score, 60);
+        assert_eq!(circle.repaid_count, 1);
+        assert_eq!(circle.defaulted_count, 1);
+        assert_eq!(circle.active_loans, 0);
+        assert_eq!(circle.open_obligations, 0);
+        assert_eq!(s.client.get_circle_limit(&m3), 599_999_940);
+        assert!(s
+            .client
+            .try_request_circle_loan(&m3, &600_000_000, &1)
+            .is_err());
+
+        // The loan was minted, so writing it off leaves the reserve alone
+        assert_eq!(s.client.write_off(&s.admin, &loan_id), 0);
+        assert_eq!(s.client.reserve_balance(), 49_950_000);
+    }
+
+    #[test]
+    fn test_leave_and_dissolve_circle() {
+        let s = setup();
+        let registry = enable_identity_checks(&s);
+        let mut members = Vec::new(&s.env);
+        for _ in 0..4 {
+            let member = new_borrower(&s);
+            verify_identity(&s, &registry, &member, VerificationLevel::Level1);
+            members.push_back(member);
+        }
+        let (m1, m2, m3, m4) = (
+            members.get(0).unwrap(),
+            members.get(1).unwrap(),
+            members.get(2).unwrap(),
+            members.get(3).unwrap(),
+        );
+        let circle_id = s.client.form_circle(&members, &5000);
+
+        // Nobody leaves while a circle loan is active
+        let loan_id = s.client.request_circle_loan(&m1, &10_000_000, &1);
+        assert_eq!(s.client.get_circle(&circle_id).active_loans, 1);
+        assert!(s.client.try_leave_circle(&m4).is_err());
+        assert!(s.client.try_dissolve_circle(&circle_id).is_err());
+        s.token.mint(&m1, &500_000);
+        s.client.pay_installment(&m1, &loan_id);
+        assert_eq!(s.client.get_circle(&circle_id).active_loans, 0);
+
+        // A member leaving frees them for another circle
+        let outsider = new_borrower(&s);
+        assert!(s.client.try_leave_circle(&outsider).is_err());
+        s.client.leave_circle(&m4);
+        assert_eq!(s.client.get_member_circle(&m4), None);
+        assert_eq!(s.client.get_circle(&circle_id).members.len(), 3);
+        assert_eq!(s.client.get_member_circle(&m1), Some(circle_id));
+
+        // Dropping under 3 members dissolves the circle, keeping its history
+        s.client.leave_circle(&m3);
+        let circle = s.client.get_circle(&circle_id);
+        assert!(circle.members.is_empty());
+        assert_eq!(circle.repaid_count, 1);
+        assert_eq!(s.client.get_member_circle(&m1), None);
+        assert_eq!(s.client.get_member_circle(&m2), None);
+        assert!(s.client.try_request_circle_loan(&m1, &10_000_000, &1).is_err());
+        assert!(s.client.try_dissolve_circle(&circle_id).is_err());
+
+        // Every member signs a dissolution
+        let circle_id = s.client.form_circle(&members, &5000);
+        s.env.set_auths(&[]);
+        assert!(s.client.try_dissolve_circle(&circle_id).is_err());
+        s.env.mock_all_auths();
+        s.client.dissolve_circle(&circle_id);
+        for member in members.iter() {
+            assert_eq!(s.client.get_member_circle(&member), None);
+        }
+    }
+
+    #[test]
+    fn test_guarantor_charged_before_default() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let guarantor = new_borrower(&s);
+        s.credit.reward(&s.admin, &guarantor, &300);
+        let some_guarantor = Some(guarantor.clone());
+
+        // 1500 USDC is over the borrower's own limit but within the guarantor's headroom
+        assert!(s
+            .client
+            .try_request_loan(&borrower, &1_500_000_000, &2, &None)
+            .is_err());
+        let loan_id = s
+            .client
+            .request_loan(&borrower, &1_500_000_000, &2, &some_guarantor);
+        assert_eq!(s.client.get_guaranteed(&guarantor), 1_500_000_000);
+
+        // The reservation also limits the guarantor's own borrowing
+        assert!(s
+            .client
+            .try_request_loan(&guarantor, &600_000_000, &1, &None)
+            .is_err());
+        assert!(s.client.try_charge_guarantor(&loan_id).is_err());
+
+        // The missed first installment is taken from the guarantor instead of defaulting
+        s.token.mint(&guarantor, &787_500_000);
+        s.token.approve(&guarantor, &s.client.address, &787_500_000, &1_000);
+        let first_deadline = (PAYMENT_PERIOD + GRACE_PERIOD + 1) as u64;
+        s.env.ledger().with_mut(|li| li.timestamp = first_deadline);
+        s.client.mark_defaulted(&loan_id);
+        let loan = s.client.get_loan(&loan_id);
+        assert_eq!(loan.status, LoanStatus::Active);
+        assert_eq!(loan.paid_payments, 1);
+        assert_eq!(s.client.get_guarantee(&loan_id).unwrap().charged, 787_500_000);
+        assert_eq!(s.token.balance(&guarantor), 0);
+        assert_eq!(s.credit.get_score_value(&borrower), 300 - 15);
+        assert_eq!(s.credit.get_score_value(&guarantor), 600 - 10);
+
+        // With the allowance spent, the second missed installment defaults the loan
+        s.env
+            .ledger()
+            .with_mut(|li| li.timestamp = first_deadline + PAYMENT_PERIOD as u64);
+        assert!(s.client.try_charge_guarantor(&loan_id).is_err());
+        s.client.mark_defaulted(&loan_id);
+        assert_eq!(s.client.get_loan(&loan_id).status, LoanStatus::Defaulted);
+        assert_eq!(s.client.get_guaranteed(&guarantor), 0);
+        assert_eq!(s.credit.get_score_value(&guarantor), 600 - 10 - 25);
+        assert_eq!(s.credit.get_score_value(&borrower), 300 - 15 - 50);
+    }
+
+    #[test]
+    fn test_guarantor_rewarded_on_repayment() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let guarantor = new_borrower(&s);
+        let some_guarantor = Some(guarantor.clone());
+        assert!(s
+            .client
+            .try_request_loan(&borrower, &10_000_000, &1, &Some(borrower.clone()))
+            .is_err());
+
+        let loan_id = s
+            .client
+            .request_loan(&borrower, &100_000_000, &1, &some_guarantor);
+        s.token.mint(&borrower, &5_000_000);
+        s.client.pay_installment(&borrower, &loan_id);
+
+        assert_eq!(s.client.get_guaranteed(&guarantor), 0);
+        assert_eq!(s.credit.get_score_value(&guarantor), 305);
+        assert_eq!(s.credit.get_score_value(&borrower), 310);
+    }
+
+    #[test]
+    fn test_guaranteed_exposure_limits_lines_and_modifications() {
+        let s = setup();
+        let borrower = new_borrower(&s);
+        let guarantor = new_borrower(&s);
+        let some_guarantor = Some(guarantor.clone());
+        let loan_id = s
+            .client
+            .request_loan(&borrower, &600_000_000, &2, &some_guarantor);
+
+        // A credit line only gets the guarantor's headroom left
+        let line = s.client.open_credit_line(&guarantor);
+        assert_eq!(line.limit, 399_999_900);
+
+        // Guaranteeing more afterwards also caps what the line can draw
+        let other = new_borrower(&s);
+        s.client.request_loan(&other, &300_000_000, &1, &some_guarantor);
+        assert!(s.client.try_draw(&guarantor, &100_000_000).is_err());
+        s.client.draw(&guarantor, &99_999_900);
+
+        // Nothing is left to open another line with
+        let line_borrower = new_borrower(&s);
+        s.client.request_loan(&line_borrower, &999_999_900, &1, &Some(other.clone()));
+        assert!(s.client.try_open_credit_line(&other).is_err());
+
+        // The guarantor has to agree to changes to the loan they back
+        let args = (s.admin.clone(), loan_id, 1u32).into_val(&s.env);
+        let invoke = MockAuthInvoke {
+            contract: &s.client.address,
+            fn_name: "grant_forbearance",
+            args,
+            sub_invokes: &[],
+        };
+        s.env.mock_auths(&[
+            MockAuth {
+                address: &s.admin,
+                invoke: &invoke,
+            },
+            MockAuth {
+                address: &borrower,
+                invoke: &invoke,
+            },
+        ]);
+        assert!(s.client.try_grant_forbearance(&s.admin, &loan_id, &1).is_err());
+
+        let args = (s.admin.clone(), loan_id, 3u32, 0i128).into_val(&s.env);
+        let invoke = MockAuthInvoke {
+            contract: &s.client.address,
+            fn_name: "restructure_loan",
+            args,
+            sub_invokes: &[],
+        };
+        s.env.mock_auths(&[
+            MockAuth {
+                address: &s.admin,
+                invoke: &invoke,
+            },
+            MockAuth {
+                address: &borrower,
+                invoke: &invoke,
+            },
+            MockAuth {
+                address: &guarantor,
+                invoke: &invoke,
+            },
+        ]);
+        s.client.restructure_loan(&s.admin, &loan_id, &3, &0);
+        assert_eq!(s.client.get_loan(&loan_id).num_payments, 3);
+    }
+}
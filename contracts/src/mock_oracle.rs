@@ -0,0 +1,104 @@
+use crate::oracle::{Asset, PriceData, PriceOracle};
+use soroban_sdk::{contract, contractimpl, symbol_short, Address, Env, Symbol};
+
+const ADMIN: Symbol = symbol_short!("ADMIN");
+const DECIMALS: Symbol = symbol_short!("DECIMALS");
+const RESOLUTION: Symbol = symbol_short!("RESOLUTN");
+
+// SEP-40 style price feed whose prices are set by hand, for development and tests
+#[contract]
+pub struct MockOracle;
+
+#[contractimpl]
+impl MockOracle {
+    /// Initialize the oracle with its admin, price decimals and update resolution
+    pub fn initialize(env: Env, admin: Address, decimals: u32, resolution: u32) {
+        if env.storage().instance().has(&ADMIN) {
+            panic!("Already initialized");
+        }
+        env.storage().instance().set(&ADMIN, &admin);
+        env.storage().instance().set(&DECIMALS, &decimals);
+        env.storage().instance().set(&RESOLUTION, &resolution);
+    }
+
+    /// Record a price for an asset at the current ledger timestamp (only admin)
+    pub fn set_price(env: Env, asset: Asset, price: i128) {
+        let timestamp = env.ledger().timestamp();
+        Self::set_price_at(env, asset, price, timestamp);
+    }
+
+    /// Record a price for an asset at a given timestamp (only admin)
+    /// Lets tests simulate a feed that stopped updating
+    pub fn set_price_at(env: Env, asset: Asset, price: i128, timestamp: u64) {
+        let admin: Address = env
+            .storage()
+            .instance()
+            .get(&ADMIN)
+            .expect("Not initialized");
+        admin.require_auth();
+
+        if price <= 0 {
+            panic!("Price must be positive");
+        }
+
+        let price_key = (symbol_short!("PRICE"), asset);
+        env.storage()
+            .persistent()
+            .set(&price_key, &PriceData { price, timestamp });
+    }
+}
+
+#[contractimpl]
+impl PriceOracle for MockOracle {
+    fn decimals(env: Env) -> u32 {
+        env.storage().instance().get(&DECIMALS).unwrap_or(14)
+    }
+
+    fn resolution(env: Env) -> u32 {
+        env.storage().instance().get(&RESOLUTION).unwrap_or(300)
+    }
+
+    fn lastprice(env: Env, asset: Asset) -> Option<PriceData> {
+        let price_key = (symbol_short!("PRICE"), asset);
+        env.storage().persistent().get(&price_key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::oracle::PriceOracleClient;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+
+    #[test]
+    fn test_mock_oracle() {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        let oracle_id = env.register(MockOracle, ());
+        let client = MockOracleClient::new(&env, &oracle_id);
+        let admin = Address::generate(&env);
+        client.initialize(&admin, &7, &300);
+
+        let token = Asset::Stellar(Address::generate(&env));
+        assert_eq!(client.lastprice(&token), None);
+
+        env.ledger().with_mut(|li| li.timestamp = 1_000);
+        client.set_price(&token, &25_000_000);
+        client.set_price_at(&Asset::Other(symbol_short!("USD")), &10_000_000, &10);
+
+        // Any SEP-40 consumer reads the same data through the interface client
+        let feed = PriceOracleClient::new(&env, &oracle_id);
+        assert_eq!(feed.decimals(), 7);
+        assert_eq!(feed.resolution(), 300);
+        let quote = feed.lastprice(&token).unwrap();
+        assert_eq!(quote.price, 25_000_000);
+        assert_eq!(quote.timestamp, 1_000);
+        assert_eq!(
+            feed.lastprice(&Asset::Other(symbol_short!("USD"))).unwrap().timestamp,
+            10
+        );
+
+        assert!(client.try_set_price(&token, &0).is_err());
+    }
+}
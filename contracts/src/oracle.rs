@@ -0,0 +1,33 @@
+use soroban_sdk::{contractclient, contracttype, Address, Env, Symbol};
+
+// Price feed interface, a subset of SEP-40 (the interface Reflector and other
+// Stellar oracles expose). Only the calls the loan manager needs are declared,
+// so any SEP-40 oracle can be plugged in.
+
+// Asset quoted by the oracle
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Asset {
+    Stellar(Address), // a token contract
+    Other(Symbol),    // an off-chain asset such as "USD"
+}
+
+// Price of an asset in the oracle's base asset, scaled by 10^decimals()
+#[contracttype]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64, // ledger timestamp the price was recorded at
+}
+
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    /// Number of decimals prices are scaled by
+    fn decimals(env: Env) -> u32;
+
+    /// Seconds between price updates
+    fn resolution(env: Env) -> u32;
+
+    /// Most recent price of an asset, or None if the oracle does not quote it
+    fn lastprice(env: Env, asset: Asset) -> Option<PriceData>;
+}
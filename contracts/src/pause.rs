@@ -0,0 +1,42 @@
+use soroban_sdk::{contracttype, symbol_short, Env, Symbol};
+
+// Emergency stop flags shared by all contracts.
+// Each flag is a bit in the PAUSED mask so operations can be halted independently
+// (e.g. borrowing paused while repayments stay open).
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum PauseFlag {
+    NewLoans = 1,
+    Repayments = 2,
+    Minting = 4,
+    IdentityCreation = 8,
+    ScoreUpdates = 16,
+}
+
+const PAUSED: Symbol = symbol_short!("PAUSED");
+
+/// Check if an operation is paused
+pub fn is_paused(env: &Env, flag: PauseFlag) -> bool {
+    let mask: u32 = env.storage().instance().get(&PAUSED).unwrap_or(0);
+    mask & (flag as u32) != 0
+}
+
+/// Panic if an operation is paused
+pub fn require_not_paused(env: &Env, flag: PauseFlag) {
+    if is_paused(env, flag) {
+        panic!("Operation is paused");
+    }
+}
+
+/// Set or clear a pause flag
+pub fn set_paused(env: &Env, flag: PauseFlag, paused: bool) {
+    let mask: u32 = env.storage().instance().get(&PAUSED).unwrap_or(0);
+    let mask = if paused {
+        mask | flag as u32
+    } else {
+        mask & !(flag as u32)
+    };
+    env.storage().instance().set(&PAUSED, &mask);
+}
+
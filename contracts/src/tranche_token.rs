@@ -0,0 +1,269 @@
+use crate::mock_usdc::{AllowanceValue, Approve, Burn, Mint, TokenError, Transfer, TransferFrom};
+use soroban_sdk::{
+    contract, contractimpl, panic_with_error, symbol_short, token::TokenInterface, Address, Env,
+    MuxedAddress, String, Symbol,
+};
+
+const POOL: Symbol = symbol_short!("POOL");
+const NAME: Symbol = symbol_short!("NAME");
+const SYMBOL: Symbol = symbol_short!("SYMBOL");
+const DECIMALS: Symbol = symbol_short!("DECIMALS");
+const TOTAL: Symbol = symbol_short!("TOTAL");
+
+// Shares of one lending pool tranche as a SEP-41 token.
+// Only the pool mints them, on deposits, and burns them, when it redeems them.
+// Holders can transfer and approve them like any other token.
+#[contract]
+pub struct TrancheShareToken;
+
+#[contractimpl]
+impl TrancheShareToken {
+    /// Initialize the share token of a tranche of `pool`
+    pub fn initialize(env: Env, pool: Address, name: String, symbol: String, decimals: u32) {
+        if env.storage().instance().has(&POOL) {
+            panic!("Already initialized");
+        }
+
+        env.storage().instance().set(&POOL, &pool);
+        env.storage().instance().set(&NAME, &name);
+        env.storage().instance().set(&SYMBOL, &symbol);
+        env.storage().instance().set(&DECIMALS, &decimals);
+    }
+
+    /// Get the pool that mints the shares
+    pub fn get_pool(env: Env) -> Address {
+        env.storage()
+            .instance()
+            .get(&POOL)
+            .expect("Not initialized")
+    }
+
+    /// Mint shares to a depositor (only the pool)
+    pub fn mint(env: Env, to: Address, amount: i128) {
+        Self::get_pool(env.clone()).require_auth();
+
+        if amount < 0 {
+            panic!("Amount cannot be negative");
+        }
+
+        let total_supply = Self::total_supply(env.clone());
+        let new_total_supply = total_supply
+            .checked_add(amount)
+            .unwrap_or_else(|| panic_with_error!(&env, TokenError::SupplyOverflow));
+        Self::receive_balance(&env, &to, amount);
+        env.storage().instance().set(&TOTAL, &new_total_supply);
+
+        Mint { to, amount }.publish(&env);
+    }
+
+    /// Get the total supply of shares
+    pub fn total_supply(env: Env) -> i128 {
+        env.storage().instance().get(&TOTAL).unwrap_or(0)
+    }
+
+    /// Read the allowance, treating expired entries as zero
+    fn read_allowance(env: &Env, from: &Address, spender: &Address) -> AllowanceValue {
+        let allowance_key = (symbol_short!("ALLOW"), (from.clone(), spender.clone()));
+        match env.storage().temporary().get::<_, AllowanceValue>(&allowance_key) {
+            Some(allowance) if allowance.expiration_ledger >= env.ledger().sequence() => allowance,
+            _ => AllowanceValue {
+                amount: 0,
+                expiration_ledger: 0,
+            },
+        }
+    }
+
+    /// Store an allowance in temporary storage that lives until it expires
+    fn write_allowance(
+        env: &Env,
+        from: &Address,
+        spender: &Address,
+        amount: i128,
+        expiration_ledger: u32,
+    ) {
+        let current_ledger = env.ledger().sequence();
+        if amount > 0 && expiration_ledger < current_ledger {
+            panic!("expiration_ledger is less than ledger seq when amount > 0");
+        }
+
+        let allowance_key = (symbol_short!("ALLOW"), (from.clone(), spender.clone()));
+        let allowance = AllowanceValue {
+            amount,
+            expiration_ledger,
+        };
+        env.storage().temporary().set(&allowance_key, &allowance);
+
+        if amount > 0 {
+            let live_for = expiration_ledger - current_ledger;
+            env.storage()
+                .temporary()
+                .extend_ttl(&allowance_key, live_for, live_for);
+        }
+    }
+
+    /// Consume part of an allowance
+    fn spend_allowance(env: &Env, from: &Address, spender: &Address, amount: i128) {
+        let allowance = Self::read_allowance(env, from, spender);
+        if allowance.amount < amount {
+            panic_with_error!(env, TokenError::InsufficientAllowance);
+        }
+        if amount > 0 {
+            Self::write_allowance(
+                env,
+                from,
+                spender,
+                allowance.amount - amount,
+                allowance.expiration_ledger,
+            );
+        }
+    }
+
+    /// Remove shares from a balance
+    fn spend_balance(env: &Env, from: &Address, amount: i128) {
+        let balance = Self::balance(env.clone(), from.clone());
+        if balance < amount {
+            panic_with_error!(env, TokenError::InsufficientBalance);
+        }
+
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("BALANCE"), from.clone()), &(balance - amount));
+    }
+
+    /// Add shares to a balance
+    fn receive_balance(env: &Env, to: &Address, amount: i128) {
+        let balance = Self::balance(env.clone(), to.clone());
+        let new_balance = balance
+            .checked_add(amount)
+            .unwrap_or_else(|| panic_with_error!(env, TokenError::BalanceOverflow));
+        env.storage()
+            .persistent()
+            .set(&(symbol_short!("BALANCE"), to.clone()), &new_balance);
+    }
+
+    /// Remove burned shares from a balance and from the total supply
+    fn burn_balance(env: &Env, from: &Address, amount: i128) {
+        Self::spend_balance(env, from, amount);
+        let total_supply = Self::total_supply(env.clone());
+        env.storage().instance().set(&TOTAL, &(total_supply - amount));
+    }
+}
+
+// Standard Soroban token interface (SEP-41), so wallets and other contracts can hold and
+// move tranche shares through `soroban_sdk::token::Client`
+#[contractimpl]
+impl TokenInterface for TrancheShareToken {
+    /// Get allowance (zero once expired)
+    fn allowance(env: Env, from: Address, spender: Address) -> i128 {
+        Self::read_allowance(&env, &from, &spender).amount
+    }
+
+    /// Approve spending until `expiration_ledger`
+    fn approve(env: Env, from: Address, spender: Address, amount: i128, expiration_ledger: u32) {
+        from.require_auth();
+
+        if amount < 0 {
+            panic!("Amount cannot be negative");
+        }
+
+        Self::write_allowance(&env, &from, &spender, amount, expiration_ledger);
+
+        Approve {
+            from,
+            spender,
+            amount,
+            expiration_ledger,
+        }
+        .publish(&env);
+    }
+
+    /// Get the shares held by an address
+    fn balance(env: Env, id: Address) -> i128 {
+        env.storage()
+            .persistent()
+            .get(&(symbol_short!("BALANCE"), id))
+            .unwrap_or(0)
+    }
+
+    /// Transfer shares
+    fn transfer(env: Env, from: Address, to: MuxedAddress, amount: i128) {
+        from.require_auth();
+
+        if amount < 0 {
+            panic!("Amount cannot be negative");
+        }
+
+        let to_address = to.address();
+        Self::spend_balance(&env, &from, amount);
+        Self::receive_balance(&env, &to_address, amount);
+
+        Transfer {
+            from,
+            to: to_address,
+            to_muxed_id: to.id(),
+            amount,
+        }
+        .publish(&env);
+    }
+
+    /// Transfer from (using allowance)
+    fn transfer_from(env: Env, spender: Address, from: Address, to: Address, amount: i128) {
+        spender.require_auth();
+
+        if amount < 0 {
+            panic!("Amount cannot be negative");
+        }
+
+        Self::spend_allowance(&env, &from, &spender, amount);
+        Self::spend_balance(&env, &from, amount);
+        Self::receive_balance(&env, &to, amount);
+
+        TransferFrom { from, to, amount }.publish(&env);
+    }
+
+    /// Burn shares from the caller's own balance (only the pool, for shares it redeems)
+    /// Holders get the value of their shares back through the pool instead.
+    fn burn(env: Env, from: Address, amount: i128) {
+        if from != Self::get_pool(env.clone()) {
+            panic!("Shares are only burned by the pool");
+        }
+        from.require_auth();
+
+        if amount < 0 {
+            panic!("Amount cannot be negative");
+        }
+
+        Self::burn_balance(&env, &from, amount);
+
+        Burn { from, amount }.publish(&env);
+    }
+
+    /// Burning with an allowance is not supported: shares are redeemed through the pool
+    fn burn_from(_env: Env, _spender: Address, _from: Address, _amount: i128) {
+        panic!("Shares are only burned by the pool");
+    }
+
+    /// Get share decimals
+    fn decimals(env: Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&DECIMALS)
+            .expect("Not initialized")
+    }
+
+    /// Get share token name
+    fn name(env: Env) -> String {
+        env.storage()
+            .instance()
+            .get(&NAME)
+            .expect("Not initialized")
+    }
+
+    /// Get share token symbol
+    fn symbol(env: Env) -> String {
+        env.storage()
+            .instance()
+            .get(&SYMBOL)
+            .expect("Not initialized")
+    }
+}
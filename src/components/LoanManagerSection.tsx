@@ -1,9 +1,18 @@
 import { useState, useMemo } from 'react';
-import { useLoanManager } from '../hooks/useLoanManager';
+import { useLoanManager, type LoanStatus } from '../hooks/useLoanManager';
 import { useWalletContext } from '../contexts/WalletContext';
 import LoanetSection from './LoanetSection';
 import './LoanManagerSection.css';
 
+const STATUS_LABELS: Record<LoanStatus, string> = {
+  Active: '🟢 Active',
+  Repaid: '✅ Paid',
+  Defaulted: '🔴 Defaulted',
+  Pending: '🕒 Pending',
+  Cancelled: '⚪ Cancelled',
+  WrittenOff: '⚫ Written off',
+};
+
 export default function LoanManagerSection() {
   const { kit, connected, address } = useWalletContext();
   const { loan, loading, requestLoan, payInstallment } = useLoanManager(
@@ -115,7 +124,7 @@ export default function LoanManagerSection() {
             <div className="detail-row">
               <span className="detail-label">Status:</span>
               <span className={`detail-value status-${loan.status.toLowerCase()}`}>
-                {loan.status === 'Active' ? '🟢 Active' : loan.status === 'Repaid' ? '✅ Paid' : '🔴 Defaulted'}
+                {STATUS_LABELS[loan.status]}
               </span>
             </div>
           </div>
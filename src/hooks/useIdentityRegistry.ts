@@ -175,7 +175,7 @@ export function useIdentityRegistry(kit: StellarWalletsKit | null, connected: bo
                 
                 const verificationLevel = fields.verification_level?._value !== undefined 
                   ? Number(fields.verification_level._value) 
-                  : (fields.verification_level?.i32 ? fields.verification_level.i32() : 0);
+                  : (fields.verification_level?.u32 ? fields.verification_level.u32() : 0);
                 
                 const verified = fields.verified?._value !== undefined 
                   ? Boolean(fields.verified._value) 
@@ -1,6 +1,6 @@
 import { useState, useEffect } from 'react';
 import { StellarWalletsKit } from '@creit.tech/stellar-wallets-kit';
-import { callSorobanContract, readSorobanContract, toScValAddress, toScValI128, toScValU32 } from '../utils/sorobanHelpers';
+import { callSorobanContract, readSorobanContract, toScValAddress, toScValI128, toScValOptionAddress, toScValU32 } from '../utils/sorobanHelpers';
 
 const LOAN_MANAGER_CONTRACT = (import.meta as any).env?.VITE_LOAN_MANAGER_CONTRACT || '';
 
@@ -13,9 +13,14 @@ export interface Loan {
   paidPayments: number;
   paymentAmount: number;
   createdAt: number;
-  status: 'Active' | 'Repaid' | 'Defaulted';
+  status: LoanStatus;
 }
 
+// Mirrors the LoanStatus contracttype enum (u32 discriminants)
+export type LoanStatus = 'Active' | 'Repaid' | 'Defaulted' | 'Pending' | 'Cancelled' | 'WrittenOff';
+
+const LOAN_STATUSES: LoanStatus[] = ['Active', 'Repaid', 'Defaulted', 'Pending', 'Cancelled', 'WrittenOff'];
+
 export function useLoanManager(kit: StellarWalletsKit | null, connected: boolean, address: string | null) {
   const [loan, setLoan] = useState<Loan | null>(null);
   const [loanId, setLoanId] = useState<number | null>(null);
@@ -95,7 +100,7 @@ export function useLoanManager(kit: StellarWalletsKit | null, connected: boolean
         let paidPayments = 0;
         let paymentAmount = 0;
         let createdAt = 0;
-        let status: 'Active' | 'Repaid' | 'Defaulted' = 'Active';
+        let status: LoanStatus = 'Active';
 
         if (loanResult._arm === 'map' || (loanResult.switch && loanResult.switch().name === 'scvMap')) {
           const map = loanResult.map();
@@ -250,17 +255,13 @@ export function useLoanManager(kit: StellarWalletsKit | null, connected: boolean
               }
             }
 
-            // status: i32 (0=Active, 1=Repaid, 2=Defaulted)
+            // status: LoanStatus enum encoded as u32 (0=Active, 1=Repaid, 2=Defaulted,
+            // 3=Pending, 4=Cancelled, 5=WrittenOff). get_loan converts legacy i32 records
+            // before returning them.
             const statusNum = fields.status?._value !== undefined 
               ? Number(fields.status._value) 
-              : (fields.status?.i32 ? fields.status.i32() : 0);
-            if (statusNum === 0) {
-              status = 'Active';
-            } else if (statusNum === 1) {
-              status = 'Repaid';
-            } else {
-              status = 'Defaulted';
-            }
+              : (fields.status?.u32 ? fields.status.u32() : (fields.status?.i32 ? fields.status.i32() : 0));
+            status = LOAN_STATUSES[statusNum] ?? 'Defaulted';
 
             console.log('Parsed loan:', {
               borrower,
@@ -303,7 +304,9 @@ export function useLoanManager(kit: StellarWalletsKit | null, connected: boolean
     }
   };
 
-  const requestLoan = async (amount: number, numPayments: number) => {
+  // A guarantor must also authorize the call and approve the loan manager to spend their
+  // tokens; this wallet only signs for the borrower.
+  const requestLoan = async (amount: number, numPayments: number, guarantor?: string) => {
     if (!kit || !connected || !address || !LOAN_MANAGER_CONTRACT) {
       throw new Error('Wallet not connected or contract not configured');
     }
@@ -317,6 +320,7 @@ export function useLoanManager(kit: StellarWalletsKit | null, connected: boolean
           toScValAddress(address),
           toScValI128(amount),
           toScValU32(numPayments),
+          toScValOptionAddress(guarantor),
         ],
       });
       await loadLoan();
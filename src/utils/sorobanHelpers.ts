@@ -468,6 +468,11 @@ export function toScValAddress(address: string): xdr.ScVal {
   }
 }
 
+// Helper to convert an optional Address string to xdr.ScVal (void for None)
+export function toScValOptionAddress(address?: string | null): xdr.ScVal {
+  return address ? toScValAddress(address) : xdr.ScVal.scvVoid();
+}
+
 // Helper to convert u32 to xdr.ScVal
 export function toScValU32(value: number): xdr.ScVal {
   return xdr.ScVal.scvU32(value);